@@ -2,7 +2,6 @@ use std::env;
 
 use chrono::{Duration, Local};
 use lgp_iot_db::models::adxl_data_v2::{init_tdengine_adxl, query_adxl_by_date};
-use taos::*;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -16,7 +15,7 @@ async fn main() -> anyhow::Result<()> {
     let last = now - Duration::minutes(30);
 
     let records =
-        query_adxl_by_date(&taos, 9999, last.timestamp_millis(), now.timestamp_millis()).await;
+        query_adxl_by_date(&taos, 9999, last.timestamp_millis(), now.timestamp_millis()).await?;
 
     for record in records {
         println!("{}", record);
@@ -2,7 +2,6 @@ use chrono::{Duration, Local};
 use lgp_iot_db::models::humiture_data_v2::{
     init_tdengine_humiture, query_humiture_by_date, query_humiture_by_group, query_humiture_by_sn,
 };
-use taos::*;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -20,18 +19,18 @@ async fn main() -> anyhow::Result<()> {
         last.timestamp_millis(),
         now.timestamp_millis(),
     )
-    .await;
+    .await?;
 
     for record in records {
         println!("{}", record);
     }
 
-    let records = query_humiture_by_group(&taos, 0, 30).await;
+    let records = query_humiture_by_group(&taos, 0, 30).await?;
     for record in records {
         println!("{}", record);
     }
 
-    let records = query_humiture_by_sn(&taos, 2, 10).await;
+    let records = query_humiture_by_sn(&taos, 2, 10).await?;
 
     for record in records {
         println!("{}", record);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE humiture_datas
+    DROP COLUMN battery,
+    DROP COLUMN people,
+    DROP COLUMN status;
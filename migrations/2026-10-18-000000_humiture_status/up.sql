@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE humiture_datas
+    ADD COLUMN battery INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN people  BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN status  INTEGER NOT NULL DEFAULT 0;
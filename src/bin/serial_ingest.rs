@@ -0,0 +1,56 @@
+// Read humiture frames from a USB-serial radio dongle and write the readings
+// to a store.
+//
+//   SERIAL_PORT  device to read, /dev/ttyUSB0 by default
+//   SERIAL_BAUD  115200 by default
+//   STORE_URL    taos://..., sqlite://..., postgres://... or memory:, see
+//                `store::open`, required
+//
+// The port is opened again with backoff whenever it goes away. To try it
+// without a dongle, link two pseudo terminals and write frames to one end:
+//
+//   socat -d -d pty,raw,echo=0,link=/tmp/ttyV0 pty,raw,echo=0,link=/tmp/ttyV1
+//   SERIAL_PORT=/tmp/ttyV0 STORE_URL=memory: RUST_APP_LOG=debug \
+//       cargo run --bin serial_ingest
+//   echo 5aa51a... | xxd -r -p > /tmp/ttyV1
+use std::env;
+
+use anyhow::Context;
+use lgp_iot_db::serial::ingest;
+use lgp_iot_db::store::{self, BufferConfig, BufferedWriter};
+use log::info;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    if env::var("RUST_APP_LOG").is_err() {
+        env::set_var("RUST_APP_LOG", "info");
+    }
+    pretty_env_logger::init_custom_env("RUST_APP_LOG");
+
+    let port = env::var("SERIAL_PORT").unwrap_or_else(|_| "/dev/ttyUSB0".to_string());
+    let baud = match env::var("SERIAL_BAUD") {
+        Ok(baud) => baud.parse()?,
+        Err(_) => 115_200,
+    };
+    let url = env::var("STORE_URL").context("STORE_URL is not set")?;
+
+    let store = store::open(&url).await?;
+    store.init().await?;
+    let writer = BufferedWriter::spawn(store, BufferConfig::default());
+
+    tokio::select! {
+        _ = ingest(&port, baud, writer.sender()) => {}
+        signal = tokio::signal::ctrl_c() => {
+            signal?;
+            info!("Stopping");
+        }
+    }
+
+    let stats = writer.shutdown().await?;
+    info!(
+        "{} readings written in {} flushes, {} failed",
+        stats.rows, stats.flushes, stats.failed
+    );
+    Ok(())
+}
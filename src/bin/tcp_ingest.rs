@@ -0,0 +1,55 @@
+// Accept humiture frames from 4G DTUs over TCP and write the readings to a
+// store.
+//
+//   LISTEN_ADDR  0.0.0.0:9000 by default
+//   STORE_URL    taos://..., sqlite://..., postgres://... or memory:, see
+//                `store::open`, required
+//   TCP_ACK      1 to answer every frame with an ACK
+//
+// Try it with `xxd -r -p <<< 5aa51a... | nc localhost 9000`.
+use std::env;
+use std::time::Duration;
+
+use anyhow::Context;
+use lgp_iot_db::server::{ServerConfig, TcpServer};
+use lgp_iot_db::store;
+use log::info;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+    if env::var("RUST_APP_LOG").is_err() {
+        env::set_var("RUST_APP_LOG", "info");
+    }
+    pretty_env_logger::init_custom_env("RUST_APP_LOG");
+
+    let addr = env::var("LISTEN_ADDR").unwrap_or_else(|_| "0.0.0.0:9000".to_string());
+    let url = env::var("STORE_URL").context("STORE_URL is not set")?;
+    let config = ServerConfig {
+        ack: env::var("TCP_ACK").is_ok_and(|ack| ack == "1"),
+        ..ServerConfig::default()
+    };
+
+    let store = store::open(&url).await?;
+    store.init().await?;
+    let server = TcpServer::bind(&addr, store, config).await?;
+
+    let stats = async {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            for conn in server.connections() {
+                info!("{:?}", conn);
+            }
+        }
+    };
+    tokio::select! {
+        result = server.run() => result?,
+        _ = stats => {}
+        signal = tokio::signal::ctrl_c() => {
+            signal?;
+            info!("Stopping");
+        }
+    }
+    Ok(())
+}
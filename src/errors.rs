@@ -1,50 +1,97 @@
-use std::{error, fmt, io, num};
+use std::{error, fmt, io};
 
+pub type BoxError = Box<dyn error::Error + Send + Sync>;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Crate level error, every public function returns this one.
 #[derive(Debug)]
-pub struct PkgError {
-    kind: String,
-    message: String,
+pub enum Error {
+    // database unreachable, connection lost or pool exhausted
+    Connection(BoxError),
+    // statement could not be prepared
+    Prepare(BoxError),
+    // table name, tags or values could not be bound
+    Bind(BoxError),
+    // the database failed to run the statement
+    Execute(BoxError),
+    // result rows could not be converted into our models
+    Decode(BoxError),
+    // malformed frame on the wire
+    Protocol(String),
+    // bad input from the caller
+    Validation(String),
+    Io(io::Error),
 }
 
-impl PkgError {
-    pub fn new(kind: String, message: String) -> Self {
-        PkgError { kind, message }
+impl Error {
+    pub fn connection<E: Into<BoxError>>(error: E) -> Self {
+        Error::Connection(error.into())
+    }
+
+    pub fn prepare<E: Into<BoxError>>(error: E) -> Self {
+        Error::Prepare(error.into())
+    }
+
+    pub fn bind<E: Into<BoxError>>(error: E) -> Self {
+        Error::Bind(error.into())
+    }
+
+    pub fn execute<E: Into<BoxError>>(error: E) -> Self {
+        Error::Execute(error.into())
+    }
+
+    pub fn decode<E: Into<BoxError>>(error: E) -> Self {
+        Error::Decode(error.into())
+    }
+
+    pub fn protocol<S: Into<String>>(message: S) -> Self {
+        Error::Protocol(message.into())
+    }
+
+    pub fn validation<S: Into<String>>(message: S) -> Self {
+        Error::Validation(message.into())
     }
 }
 
-impl fmt::Display for PkgError {
+impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.kind as &str {
-            "pkg" => write!(
-                f,
-                "Packcage Error:{{Kind: {}, Message: {}}}",
-                self.kind, self.message
-            ),
-            _ => write!(f, "Sorry, something is wrong! Please Try Again!"),
+        match self {
+            Error::Connection(e) => write!(f, "connection error: {}", e),
+            Error::Prepare(e) => write!(f, "prepare error: {}", e),
+            Error::Bind(e) => write!(f, "bind error: {}", e),
+            Error::Execute(e) => write!(f, "execute error: {}", e),
+            Error::Decode(e) => write!(f, "decode error: {}", e),
+            Error::Protocol(message) => write!(f, "protocol error: {}", message),
+            Error::Validation(message) => write!(f, "validation error: {}", message),
+            Error::Io(e) => write!(f, "io error: {}", e),
         }
     }
 }
 
-impl error::Error for PkgError {
+impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        Some(self)
+        match self {
+            Error::Connection(e)
+            | Error::Prepare(e)
+            | Error::Bind(e)
+            | Error::Execute(e)
+            | Error::Decode(e) => Some(e.as_ref()),
+            Error::Io(e) => Some(e),
+            Error::Protocol(_) | Error::Validation(_) => None,
+        }
     }
 }
 
-impl From<io::Error> for PkgError {
+impl From<io::Error> for Error {
     fn from(error: io::Error) -> Self {
-        PkgError {
-            kind: String::from("io"),
-            message: error.to_string(),
-        }
+        Error::Io(error)
     }
 }
 
-impl From<num::ParseIntError> for PkgError {
-    fn from(error: num::ParseIntError) -> Self {
-        PkgError {
-            kind: String::from("parse"),
-            message: error.to_string(),
-        }
+// a blocking backend task panicked or was cancelled
+impl From<tokio::task::JoinError> for Error {
+    fn from(error: tokio::task::JoinError) -> Self {
+        Error::Execute(error.into())
     }
 }
@@ -1,9 +1,20 @@
-// use diesel::prelude::*;
-// use diesel::r2d2::{self, ConnectionManager};
+#[cfg(feature = "postgres")]
+use diesel::prelude::*;
+#[cfg(feature = "postgres")]
+use diesel::r2d2::{self, ConnectionManager};
 
 pub mod errors;
 pub mod models;
-// pub mod schema;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod protocol;
+pub mod query;
+#[cfg(feature = "postgres")]
+pub mod schema;
+pub mod serial;
+pub mod server;
+pub mod store;
+pub mod tdengine;
 
-// pub type DbError = Box<dyn std::error::Error + Send + Sync>;
-// pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+#[cfg(feature = "postgres")]
+pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
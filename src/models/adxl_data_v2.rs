@@ -1,13 +1,16 @@
 use std::fmt;
 
 use chrono::{DateTime, Local};
-use log::error;
 use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 
-use taos::*;
+use crate::errors::Result;
+use crate::protocol::adxl::{decode, encode};
+use crate::protocol::{CrcMode, DecodeError};
+use crate::query::check_limit;
+use crate::store::{SensorStore, TdengineStore};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdxlData {
     pub device_id: i32,
     pub ts: DateTime<Local>,
@@ -54,105 +57,70 @@ impl AdxlData {
             bat: 100.0,
         }
     }
-}
 
-pub async fn init_tdengine_adxl(
-    database_url: &str,
-    db_name: &str,
-) -> anyhow::Result<Taos, taos::Error> {
-    let builder = TaosBuilder::from_dsn(database_url).unwrap();
-    let taos = builder.build().await.unwrap();
-    taos.create_database(db_name).await.unwrap();
-    taos.use_database(db_name).await.unwrap();
-    taos::sync::Queryable::exec(
-        &taos,
-        "CREATE STABLE if NOT EXISTS adxl355 (
-    ts        TIMESTAMP ,
-    device_id INT       ,
-    x         FLOAT     ,
-    y         FLOAT     , 
-    z         FLOAT     ,
-    t         FLOAT     ,
-    bat       FLOAT     )
-    TAGS     (groupId INT)
-    ",
-    )
-    .unwrap();
+    // a frame holding this sample alone
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // one sample of one device always encodes
+        encode(std::slice::from_ref(self)).unwrap()
+    }
 
-    Ok(taos)
-}
+    // the samples of one frame, see `protocol::adxl`
+    pub fn from_bytes(bytes: &[u8]) -> std::result::Result<Vec<Self>, DecodeError> {
+        Ok(decode(bytes, CrcMode::Strict)?.samples)
+    }
 
-pub async fn insert_adxl(new_data: AdxlData, taos: &Taos) -> Result<usize, Error> {
-    let mut stmt = Stmt::init(&taos).await.unwrap();
-    stmt.prepare("INSERT INTO ? USING adxl355 TAGS(?) VALUES(?, ?, ?, ?, ?, ?, ?)")
-        .await
-        .unwrap();
+    // one frame of up to 16 evenly spaced samples, oldest first
+    pub fn encode(datas: &[Self]) -> Result<Vec<u8>> {
+        encode(datas)
+    }
+}
 
-    // bind table name and tags
-    stmt.set_tbname_tags(
-        format!("g{:06}", new_data.device_id).as_str(),
-        &[taos::Value::Int(new_data.device_id)],
-    )
-    .await
-    .unwrap();
+// TDengine helpers, the returned handle remembers `db_name` and every insert
+// and query below goes to `<db_name>.adxl355`.
+pub async fn init_tdengine_adxl(database_url: &str, db_name: &str) -> Result<TdengineStore> {
+    let store = TdengineStore::connect(database_url)
+        .await?
+        .with_adxl_table(db_name, "adxl355")?;
+    store.init_adxl().await?;
 
-    // bind values.
-    let values = vec![
-        ColumnView::from_millis_timestamp(vec![new_data.ts.timestamp_millis()]),
-        ColumnView::from_ints(vec![new_data.device_id]),
-        ColumnView::from_floats(vec![new_data.x]),
-        ColumnView::from_floats(vec![new_data.y]),
-        ColumnView::from_floats(vec![new_data.z]),
-        ColumnView::from_floats(vec![new_data.t]),
-        ColumnView::from_floats(vec![new_data.bat]),
-    ];
+    Ok(store)
+}
 
-    stmt.bind(&values).await.unwrap();
-    stmt.add_batch().await.unwrap();
-    // execute.
-    let rows = stmt.execute().await.unwrap();
+pub async fn insert_adxl(new_data: AdxlData, store: &TdengineStore) -> Result<usize> {
+    store.insert_adxl(&new_data).await
+}
 
-    Ok(rows)
+// samples are grouped per g{device_id} sub table, returns the total rows written
+pub async fn insert_adxl_batch(datas: &[AdxlData], store: &TdengineStore) -> Result<usize> {
+    store.insert_adxl_batch(datas).await
 }
 
 pub async fn query_adxl_by_date(
-    taos: &Taos,
+    store: &TdengineStore,
     device_id: i32,
     start_date: i64,
     end_date: i64,
-) -> Vec<AdxlData> {
-    let sql = format!(
-        "SELECT * FROM adxl355.adxl355 WHERE device_id={} AND ts BETWEEN {} AND {} ORDER BY ts DESC;",
-        device_id, start_date, end_date
-    );
-    query_adxl(taos, &sql).await
-}
-
-pub async fn query_adxl_by_group(taos: &Taos, group_id: i32, limit: i32) -> Vec<AdxlData> {
-    let sql = format!(
-        "SELECT * FROM adxl355.{} ORDER BY ts DESC LIMIT {}",
-        format!("g{:06}", group_id),
-        limit
-    );
-    query_adxl(taos, &sql).await
+) -> Result<Vec<AdxlData>> {
+    store
+        .query_adxl_range(device_id, start_date, end_date)
+        .await
 }
 
-pub async fn query_adxl_by_id(taos: &Taos, device_id: i32, limit: i32) -> Vec<AdxlData> {
-    let sql = format!(
-        "SELECT * FROM adxl355.adxl355 WHERE device_id={} ORDER BY ts DESC LIMIT {}",
-        device_id, limit
-    );
-    query_adxl(taos, &sql).await
+// the sub table of a device is tagged with its id, so group == device here
+pub async fn query_adxl_by_group(
+    store: &TdengineStore,
+    group_id: i32,
+    limit: i32,
+) -> Result<Vec<AdxlData>> {
+    store.query_adxl_latest(group_id, check_limit(limit)?).await
 }
 
-async fn query_adxl(taos: &Taos, sql: &str) -> Vec<AdxlData> {
-    let mut result = taos.query(sql).await.unwrap();
-    let mut records = Vec::new();
-    match result.deserialize().try_collect().await {
-        Ok(nrecords) => records = nrecords,
-        Err(e) => {
-            error!("query error: {:?}", e);
-        }
-    }
-    records
+pub async fn query_adxl_by_id(
+    store: &TdengineStore,
+    device_id: i32,
+    limit: i32,
+) -> Result<Vec<AdxlData>> {
+    store
+        .query_adxl_latest(device_id, check_limit(limit)?)
+        .await
 }
@@ -4,7 +4,8 @@ use log::info;
 use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::{schema::adxl_datas, DbError};
+use crate::errors::{Error, Result};
+use crate::schema::adxl_datas;
 
 #[derive(Serialize, Deserialize, Queryable, Debug, AsChangeset)]
 pub struct AdxlData {
@@ -79,28 +80,33 @@ impl NewAdxlData {
 }
 
 impl AdxlData {
-    pub fn all(conn: &mut PgConnection) -> Result<Vec<AdxlData>, DbError> {
-        let items = adxl_datas::table.load::<AdxlData>(conn)?;
+    pub fn all(conn: &mut PgConnection) -> Result<Vec<AdxlData>> {
+        let items = adxl_datas::table
+            .load::<AdxlData>(conn)
+            .map_err(Error::execute)?;
         Ok(items)
     }
 
-    pub fn find(id: i32, conn: &mut PgConnection) -> Result<AdxlData, DbError> {
-        let result = adxl_datas::table.find(id).first::<AdxlData>(conn)?;
+    pub fn find(id: i32, conn: &mut PgConnection) -> Result<AdxlData> {
+        let result = adxl_datas::table
+            .find(id)
+            .first::<AdxlData>(conn)
+            .map_err(Error::execute)?;
         Ok(result)
     }
 
-    pub fn create(data: NewAdxlData, conn: &mut PgConnection) -> Result<AdxlData, DbError> {
+    pub fn create(data: NewAdxlData, conn: &mut PgConnection) -> Result<AdxlData> {
         let result = diesel::insert_into(adxl_datas::table)
             .values(&data)
             .get_result(conn)
-            .expect("Error on Create");
+            .map_err(Error::execute)?;
         Ok(result)
     }
 
-    pub fn delete(id: i32, conn: &mut PgConnection) -> Result<usize, DbError> {
+    pub fn delete(id: i32, conn: &mut PgConnection) -> Result<usize> {
         let num_deleted = diesel::delete(adxl_datas::table.find(id))
             .execute(conn)
-            .expect("Error on Delete");
+            .map_err(Error::execute)?;
         Ok(num_deleted)
     }
 }
@@ -1,12 +1,15 @@
-use chrono::{DateTime, Datelike, Duration, Local, Timelike};
-use crc::{Crc, CRC_8_MAXIM_DOW};
-use log::{debug, error, warn};
+use crate::errors::Result;
+pub use crate::protocol::humiture::DecodeOptions;
+use crate::protocol::humiture::{decode, decode_frame, encode, DecodeError};
+use crate::query::check_limit;
+use crate::store::{SensorStore, TdengineStore};
+use chrono::{DateTime, Local};
+use log::error;
 use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 use std::fmt;
-use taos::*;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HumitureData {
     pub ts: DateTime<Local>, // Time Stamp from device
     pub sn: i32,             // Device Serial Number
@@ -15,6 +18,12 @@ pub struct HumitureData {
     pub type_id: i32,        // Type
     pub temperature: f32,
     pub humidity: f32,
+    #[serde(default)]
+    pub battery: i32, // Battery level, %
+    #[serde(default)]
+    pub people: bool, // Occupancy, bit 0 of the status byte
+    #[serde(default)]
+    pub status: i32, // Raw status byte, bits 1..3 hold the interval code
 }
 
 // print
@@ -22,14 +31,17 @@ impl fmt::Display for HumitureData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "HumitureData {{ sn: 0x{:08X}, id: 0x{:016X}, group: 0x{:02X}, type: 0x{:02X}, ts: {}, t: {}℃, h: {}% }}",
+            "HumitureData {{ sn: 0x{:08X}, id: 0x{:016X}, group: 0x{:02X}, type: 0x{:02X}, ts: {}, t: {}℃, h: {}%, bat: {}%, people: {}, status: 0x{:02X} }}",
             self.sn,
             self.device_id,
             self.group_id,
             self.type_id,
             self.ts,
             self.temperature,
-            self.humidity
+            self.humidity,
+            self.battery,
+            self.people,
+            self.status
         )
     }
 }
@@ -44,6 +56,9 @@ impl HumitureData {
             type_id,
             temperature: t,
             humidity: h,
+            battery: 0x63,
+            people: false,
+            status: 0,
         }
     }
 
@@ -57,6 +72,9 @@ impl HumitureData {
             type_id: 0,
             temperature: rng.gen_range(-20.0..50.0),
             humidity: rng.gen_range(1.0..100.0),
+            battery: 0x63,
+            people: false,
+            status: 0,
         }
     }
 
@@ -70,262 +88,106 @@ impl HumitureData {
             type_id: 0,
             temperature: r * (angle * 3.1415926 / 180.0).sin(),
             humidity: r * (angle * 3.1415926 / 180.0).cos(),
+            battery: 0x63,
+            people: false,
+            status: 0,
         }
     }
 
-    // convert to bytes
-    pub fn to_bytes(self) -> Vec<u8> {
-        let mut bytes: Vec<u8> = Vec::new();
-
-        // header
-        bytes.push(0x5A);
-        bytes.push(0xA5);
-        // length
-        bytes.push(26);
-        // id
-        bytes.extend_from_slice(&self.device_id.to_be_bytes());
-        // sn
-        bytes.extend_from_slice(&self.sn.to_be_bytes());
-        // group id
-        bytes.push(self.group_id as u8);
-
-        // debug!("bytes length = {}", bytes.len());
-
-        // type
-        bytes.push(self.type_id as u8);
-
-        // date
-        let dt = self.ts;
-        let hex_date = format!(
-            "{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
-            dt.year() - 2000,
-            dt.month(),
-            dt.day(),
-            dt.hour(),
-            dt.minute(),
-            dt.second()
-        );
-        bytes.extend_from_slice(&hex::decode(&hex_date).unwrap());
-
-        // debug!("bytes length = {}", bytes.len());
-
-        // temperature & humidity
-        let temperature_x10 = (self.temperature * 10.0) as i16;
-        let humidity_x10 = (self.humidity * 10.0) as i16;
-        bytes.extend_from_slice(&temperature_x10.to_be_bytes());
-        bytes.extend_from_slice(&humidity_x10.to_be_bytes());
-
-        // battery
-        bytes.push(0x63);
-        // people
-        bytes.push(0x00);
-
-        // crc
-        let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
-        let crc = crc8_checksum.checksum(&bytes[3..29]);
-
-        bytes.push(crc);
-
-        // debug!("bytes length = {}", bytes.len());
-
-        bytes
+    // convert to a frame of one reading, the interval code is kept from the
+    // status byte. Fails for a time the frame cannot hold, before 2000 or
+    // after 2255
+    pub fn to_bytes(self) -> Result<Vec<u8>> {
+        let code = ((self.status >> 1) & 0x07) as u8;
+        encode(std::slice::from_ref(&self), code)
     }
 
-    // get some data from bytes
+    // get some data from bytes, timestamped from the device clock
     pub fn from_bytes(bytes: &[u8], n: i32) -> Vec<Self> {
-        let mut result = Vec::new();
-
-        // unpackage the data
-        if bytes.len() > 0 {
-            // compare the head
-            if bytes[0..2] == [0x5A, 0xA5] {
-                // check the length
-                let len = bytes[2] as usize;
-                if len == bytes.len() - 4 {
-                    let device_id = i64::from_be_bytes(bytes[3..11].try_into().unwrap());
-                    let sn = i32::from_be_bytes(bytes[11..15].try_into().unwrap());
-                    let group_id = bytes[15] as i32;
-                    let type_id = bytes[16] as i32;
-
-                    // Time Interval
-                    let interval = if len == 70 {
-                        // 12 datas
-                        ((bytes[72] as u8) >> 1) & 0x07
-                    } else if len == 118 {
-                        // 24 datas
-                        ((bytes[120] as u8) >> 1) & 0x07
-                    } else {
-                        // single data
-                        ((bytes[28] as u8) >> 1) & 0x07
-                    };
-
-                    // 000 -> 5min
-                    // 001 -> 10min
-                    // .........
-                    // 111 -> 40min
-                    let interval = ((interval + 1) * 5) as i32;
-
-                    // get current time
-                    let now = Local::now();
-
-                    for i in 0..n {
-                        let tt = i16::from_be_bytes([
-                            bytes[(23 + i * 2) as usize],
-                            bytes[(24 + i * 2) as usize],
-                        ]);
-                        let hh = i16::from_be_bytes([
-                            bytes[(23 + i * 2 + 2 * n) as usize],
-                            bytes[(24 + i * 2 + 2 * n) as usize],
-                        ]);
-                        let t = tt as f32 / 10.0;
-                        let h = hh as f32 / 10.0;
+        Self::from_bytes_with(bytes, n, &DecodeOptions::default())
+    }
 
-                        // seperate the time(1h/2h/4h) into n slices
-                        let ts = now - Duration::minutes((((n - i) * interval) as i32).into());
+    // samples of one raw frame of any layout, out of range values left out
+    pub fn decode(bytes: &[u8]) -> std::result::Result<Vec<Self>, DecodeError> {
+        Ok(decode(bytes, &DecodeOptions::default())?.samples)
+    }
 
-                        let new_data = HumitureData {
-                            sn,
-                            ts,
-                            device_id,
-                            group_id,
-                            type_id,
-                            temperature: t,
-                            humidity: h,
-                        };
+    // one frame of 1, 12 or 24 readings, oldest first, see `protocol::encode`
+    pub fn encode(datas: &[Self], interval_code: u8) -> Result<Vec<u8>> {
+        encode(datas, interval_code)
+    }
 
-                        // temperature and humidity check
-                        if t > 100.0 || t < -40.0 || h > 100.0 || h < 0.0 {
-                            // check if test data
-                            if group_id == 0 && type_id == 0 {
-                                debug!("{}", new_data);
-                                result.push(new_data);
-                            } else {
-                                warn!("{} --- Overflow!", new_data)
-                            }
-                        } else {
-                            debug!("{}", new_data);
-                            result.push(new_data);
-                        }
-                    }
-                } else {
-                    error!(
-                        "length error : real is {}, total len is {}",
-                        len,
-                        bytes.len()
-                    );
-                }
-            } else {
-                error!("bad header");
+    // lossy wrapper around `decode_frame`, bad frames are logged and give
+    // no samples
+    pub fn from_bytes_with(bytes: &[u8], n: i32, options: &DecodeOptions) -> Vec<Self> {
+        let n = usize::try_from(n).unwrap_or(0);
+        match decode_frame(bytes, n, options) {
+            Ok(frame) => frame.samples,
+            Err(e) => {
+                error!("{}", e);
+                Vec::new()
             }
         }
-
-        return result;
     }
 }
 
 impl HumitureData {}
 
-pub async fn init_tdengine_humiture(database_url: &str, db_name: &str) -> Result<Taos, Error> {
-    let taos = TaosBuilder::from_dsn(database_url)?.build().await?;
-    taos.create_database(db_name).await?;
-    taos.use_database(db_name).await?;
-    taos.exec(
-        "CREATE STABLE if NOT EXISTS humiture (
-    ts          TIMESTAMP,
-    sn          INT      ,
-    device_id   BIGINT   ,
-    group_id    INT      ,
-    type_id     INT      ,
-    temperature FLOAT    ,
-    humidity    FLOAT    )
-    TAGS     (groupId INT)
-    ",
-    )
-    .await?;
+// TDengine helpers, the returned handle remembers `db_name` and every insert
+// and query below goes to `<db_name>.humiture`.
+pub async fn init_tdengine_humiture(database_url: &str, db_name: &str) -> Result<TdengineStore> {
+    let store = TdengineStore::connect(database_url)
+        .await?
+        .with_humiture_table(db_name, "humiture")?;
+    store.init_humiture().await?;
 
-    Ok(taos)
+    Ok(store)
 }
 
-pub async fn insert_humiture(new_data: HumitureData, taos: &Taos) -> Result<usize, Error> {
-    let mut stmt = Stmt::init(&taos).await.unwrap();
-    stmt.prepare("INSERT INTO ? USING humiture TAGS(?) VALUES(?, ?, ?, ?, ?, ?, ?)")
-        .await
-        .unwrap();
-
-    // bind table name and tags
-    stmt.set_tbname_tags(
-        format!("g{:06}", new_data.group_id).as_str(),
-        &[taos::Value::Int(new_data.group_id)],
-    )
-    .await
-    .unwrap();
-
-    // bind values.
-    let values = vec![
-        ColumnView::from_millis_timestamp(vec![new_data.ts.timestamp_millis()]),
-        ColumnView::from_ints(vec![new_data.sn]),
-        ColumnView::from_big_ints(vec![new_data.device_id]),
-        ColumnView::from_ints(vec![new_data.group_id]),
-        ColumnView::from_ints(vec![new_data.type_id]),
-        ColumnView::from_floats(vec![new_data.temperature]),
-        ColumnView::from_floats(vec![new_data.humidity]),
-    ];
-
-    stmt.bind(&values).await.unwrap();
-    stmt.add_batch().await.unwrap();
-    // execute.
-    let rows = stmt.execute().await.unwrap();
-
-    debug!("Inserted {} rows", rows);
+pub async fn insert_humiture(new_data: HumitureData, store: &TdengineStore) -> Result<usize> {
+    store.insert_humiture(&new_data).await
+}
 
-    Ok(rows)
+// all readings of a frame in one statement execution
+pub async fn insert_humiture_batch(datas: &[HumitureData], store: &TdengineStore) -> Result<usize> {
+    store.insert_humiture_batch(datas).await
 }
 
 pub async fn query_humiture_by_date(
-    taos: &Taos,
+    store: &TdengineStore,
     device_id: i64,
     start_date: i64,
     end_date: i64,
-) -> Vec<HumitureData> {
-    let sql = format!(
-        "SELECT * FROM humiture.humiture WHERE device_id={} AND ts BETWEEN {} AND {} ORDER BY ts DESC;",
-        device_id, start_date, end_date
-    );
-    query_humiture(taos, &sql).await
-}
-
-pub async fn query_humiture_by_sn(taos: &Taos, sn: i32, limit: i32) -> Vec<HumitureData> {
-    let sql = format!(
-        "SELECT * FROM humiture.humiture WHERE sn={} ORDER BY ts DESC LIMIT {}",
-        sn, limit
-    );
-    query_humiture(taos, &sql).await
+) -> Result<Vec<HumitureData>> {
+    store
+        .query_humiture_range(device_id, start_date, end_date)
+        .await
 }
 
-pub async fn query_humiture_by_group(taos: &Taos, group_id: i32, limit: i32) -> Vec<HumitureData> {
-    let sql = format!(
-        "SELECT * FROM humiture.humiture WHERE group_id={} ORDER BY ts DESC LIMIT {}",
-        group_id, limit
-    );
-    query_humiture(taos, &sql).await
+pub async fn query_humiture_by_sn(
+    store: &TdengineStore,
+    sn: i32,
+    limit: i32,
+) -> Result<Vec<HumitureData>> {
+    store.query_humiture_by_sn(sn, check_limit(limit)?).await
 }
 
-pub async fn query_humiture_by_id(taos: &Taos, device_id: i64, limit: i32) -> Vec<HumitureData> {
-    let sql = format!(
-        "SELECT * FROM humiture.humiture WHERE device_id={} ORDER BY ts DESC LIMIT {}",
-        device_id, limit
-    );
-    query_humiture(taos, &sql).await
+pub async fn query_humiture_by_group(
+    store: &TdengineStore,
+    group_id: i32,
+    limit: i32,
+) -> Result<Vec<HumitureData>> {
+    store
+        .query_humiture_by_group(group_id, check_limit(limit)?)
+        .await
 }
 
-async fn query_humiture(taos: &Taos, sql: &str) -> Vec<HumitureData> {
-    let mut result = taos.query(sql).await.unwrap();
-    let mut records = Vec::new();
-    match result.deserialize().try_collect().await {
-        Ok(nrecords) => records = nrecords,
-        Err(e) => {
-            error!("query error: {:?}", e);
-        }
-    }
-    records
+pub async fn query_humiture_by_id(
+    store: &TdengineStore,
+    device_id: i64,
+    limit: i32,
+) -> Result<Vec<HumitureData>> {
+    store
+        .query_humiture_latest(device_id, check_limit(limit)?)
+        .await
 }
@@ -6,7 +6,8 @@ use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 use std::fmt;
 
-use crate::{schema::humiture_datas, DbError};
+use crate::errors::{Error, Result};
+use crate::schema::humiture_datas;
 
 // average
 pub fn average(d0: f32, d1: f32, d2: f32, threshold: f32) -> f32 {
@@ -53,6 +54,9 @@ pub struct HumitureData {
     pub ts: NaiveDateTime, // Time Stamp from device
     pub temperature: f32,
     pub humidity: f32,
+    pub battery: i32, // Battery level, %
+    pub people: bool, // Occupancy
+    pub status: i32,  // Raw status byte
 }
 
 #[derive(Debug, Insertable, Serialize, Deserialize)]
@@ -65,6 +69,9 @@ pub struct NewHumitureData {
     pub ts: NaiveDateTime, // Time Stamp fro device
     pub temperature: f32,
     pub humidity: f32,
+    pub battery: i32, // Battery level, %
+    pub people: bool, // Occupancy
+    pub status: i32,  // Raw status byte
 }
 
 // print
@@ -96,6 +103,9 @@ impl NewHumitureData {
             type_id,
             temperature: t,
             humidity: h,
+            battery: 0x63,
+            people: false,
+            status: 0,
         }
     }
 
@@ -113,6 +123,9 @@ impl NewHumitureData {
             type_id: 0,
             temperature: rng.gen_range(-20.0..50.0),
             humidity: rng.gen_range(1.0..100.0),
+            battery: 0x63,
+            people: false,
+            status: 0,
         }
     }
 
@@ -130,6 +143,9 @@ impl NewHumitureData {
             type_id: 0,
             temperature: r * (angle * 3.1415926 / 180.0).sin(),
             humidity: r * (angle * 3.1415926 / 180.0).cos(),
+            battery: 0x63,
+            people: false,
+            status: 0,
         }
     }
 
@@ -176,9 +192,9 @@ impl NewHumitureData {
         bytes.extend_from_slice(&humidity_x10.to_be_bytes());
 
         // battery
-        bytes.push(0x63);
+        bytes.push(self.battery as u8);
         // people
-        bytes.push(0x00);
+        bytes.push((self.status as u8 & !0x01) | u8::from(self.people));
 
         // crc
         let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
@@ -225,6 +241,11 @@ impl NewHumitureData {
                     // 111 -> 40min
                     let interval = ((interval + 1) * 5) as i32;
 
+                    // battery and status follow the samples
+                    let battery = bytes[(23 + 4 * n) as usize] as i32;
+                    let status = bytes[(24 + 4 * n) as usize];
+                    let people = status & 0x01 != 0;
+
                     // get current time
                     let fmt = "%Y-%m-%d %H:%M:%S";
                     let naive = Local::now().format(fmt).to_string();
@@ -257,6 +278,9 @@ impl NewHumitureData {
                                     type_id,
                                     temperature: t,
                                     humidity: h,
+                                    battery,
+                                    people,
+                                    status: status as i32,
                                 };
                                 debug!("{}", new_data);
                                 // add the vector
@@ -265,7 +289,7 @@ impl NewHumitureData {
                                 warn!(
                                 "Humiture Overflow! t:{}, h:{}, ts:{}, id:{}, sn:{}, group:{}, type:{}",
                                 t,h,
-                                ts.to_string(),
+                                ts,
                                 hex::encode(id),
                                 hex::encode(sn),
                                 group_id,
@@ -281,6 +305,9 @@ impl NewHumitureData {
                                 type_id,
                                 temperature: t,
                                 humidity: h,
+                                battery,
+                                people,
+                                status: status as i32,
                             };
 
                             debug!("{}", new_data);
@@ -306,28 +333,33 @@ impl NewHumitureData {
 }
 
 impl HumitureData {
-    pub fn all(conn: &mut PgConnection) -> Result<Vec<HumitureData>, DbError> {
-        let items = humiture_datas::table.load::<Self>(conn)?;
+    pub fn all(conn: &mut PgConnection) -> Result<Vec<HumitureData>> {
+        let items = humiture_datas::table
+            .load::<Self>(conn)
+            .map_err(Error::execute)?;
         Ok(items)
     }
 
-    pub fn find(id: i32, conn: &mut PgConnection) -> Result<HumitureData, DbError> {
-        let result = humiture_datas::table.find(id).first::<HumitureData>(conn)?;
+    pub fn find(id: i32, conn: &mut PgConnection) -> Result<HumitureData> {
+        let result = humiture_datas::table
+            .find(id)
+            .first::<HumitureData>(conn)
+            .map_err(Error::execute)?;
         Ok(result)
     }
 
-    pub fn create(data: NewHumitureData, conn: &mut PgConnection) -> Result<HumitureData, DbError> {
+    pub fn create(data: NewHumitureData, conn: &mut PgConnection) -> Result<HumitureData> {
         let result = diesel::insert_into(humiture_datas::table)
             .values(&data)
             .get_result(conn)
-            .expect("Error on Create");
+            .map_err(Error::execute)?;
         Ok(result)
     }
 
-    pub fn delete(id: i32, conn: &mut PgConnection) -> Result<usize, DbError> {
+    pub fn delete(id: i32, conn: &mut PgConnection) -> Result<usize> {
         let num_deleted = diesel::delete(humiture_datas::table.find(id))
             .execute(conn)
-            .expect("Error on Delete");
+            .map_err(Error::execute)?;
         Ok(num_deleted)
     }
 }
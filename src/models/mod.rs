@@ -1,4 +1,7 @@
 pub mod adxl_data_v2;
-// pub mod adxl_datas;
+#[cfg(feature = "postgres")]
+pub mod adxl_datas;
 pub mod humiture_data_v2;
-// pub mod humiture_datas;
+#[cfg(feature = "postgres")]
+pub mod humiture_datas;
+pub mod reading;
@@ -0,0 +1,22 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+
+// any reading the ingestion path hands over to storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Reading {
+    Humiture(HumitureData),
+    Adxl(AdxlData),
+}
+
+impl From<HumitureData> for Reading {
+    fn from(data: HumitureData) -> Self {
+        Reading::Humiture(data)
+    }
+}
+
+impl From<AdxlData> for Reading {
+    fn from(data: AdxlData) -> Self {
+        Reading::Adxl(data)
+    }
+}
@@ -4,14 +4,23 @@ use std::ops::Deref;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 
-type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
-pub fn init_pool() -> Pool {
-    let manager = ConnectionManager::<PgConnection>::new(database_url());
-    Pool::new(manager).expect("db pool")
+use crate::errors::{Error, Result};
+use crate::DbPool;
+
+// pool on $DATABASE_URL
+pub fn init_pool() -> Result<DbPool> {
+    build_pool(&database_url()?)
 }
-fn database_url() -> String {
-    env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+
+pub fn build_pool(database_url: &str) -> Result<DbPool> {
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    DbPool::new(manager).map_err(Error::connection)
 }
+
+fn database_url() -> Result<String> {
+    env::var("DATABASE_URL").map_err(|_| Error::connection("DATABASE_URL must be set"))
+}
+
 pub struct DbConn(pub r2d2::PooledConnection<ConnectionManager<PgConnection>>);
 
 impl Deref for DbConn {
@@ -0,0 +1,174 @@
+use chrono::{DateTime, Duration, Local, TimeZone};
+use log::warn;
+
+use crate::errors::{Error, Result as CrateResult};
+use crate::models::adxl_data_v2::AdxlData;
+use crate::protocol::crc8;
+use crate::protocol::humiture::{CrcMode, DecodeError, OVERHEAD};
+
+// 0x5A 0xA6 | len | device_id i32 | ts i64 | interval u16 | count
+// | count x (x f32 | y f32 | z f32 | t i16 | bat) | crc8
+//
+// Like the humiture frames, `len` counts the bytes between itself and the crc
+// and the crc covers the same bytes. Every value is big endian. `ts` is the
+// time of the first sample in unix milliseconds and the samples follow
+// `interval` milliseconds apart. x / y / z are in g, t is x100 in ℃ and bat
+// is the battery level in %.
+pub const HEADER: [u8; 2] = [0x5A, 0xA6];
+
+pub const MAX_SAMPLES: usize = 16;
+
+// offset of the first sample and the bytes of each one
+const SAMPLES: usize = 18;
+const SAMPLE_LEN: usize = 15;
+
+// value of the len byte for a frame of `n` samples, 255 at most
+pub const fn payload_len(n: usize) -> usize {
+    15 + SAMPLE_LEN * n
+}
+
+// one decoded frame
+#[derive(Debug, Clone)]
+pub struct AdxlFrame {
+    pub device_id: i32,
+    // time of the first sample
+    pub ts: DateTime<Local>,
+    // milliseconds between two samples
+    pub interval: u16,
+    // false if the checksum did not match and the frame was decoded anyway
+    pub crc_ok: bool,
+    pub samples: Vec<AdxlData>,
+}
+
+// Encode samples of one device, oldest first and evenly spaced, into a frame.
+// The spacing is rounded to whole milliseconds.
+pub fn encode(samples: &[AdxlData]) -> CrateResult<Vec<u8>> {
+    let n = samples.len();
+    if n == 0 || n > MAX_SAMPLES {
+        return Err(DecodeError::UnsupportedSampleCount(n).into());
+    }
+    let first = &samples[0];
+    if let Some(other) = samples.iter().find(|s| s.device_id != first.device_id) {
+        return Err(Error::validation(format!(
+            "samples of devices {} and {} in one frame",
+            first.device_id, other.device_id
+        )));
+    }
+    let interval = match samples {
+        [a, b, ..] => (b.ts - a.ts).num_milliseconds(),
+        _ => 0,
+    };
+    let interval = u16::try_from(interval)
+        .map_err(|_| Error::validation(format!("interval out of range: {} ms", interval)))?;
+    let step = Duration::milliseconds(i64::from(interval));
+    if samples.windows(2).any(|w| w[1].ts - w[0].ts != step) {
+        return Err(Error::validation(format!(
+            "samples are not {} ms apart",
+            interval
+        )));
+    }
+
+    let mut bytes = Vec::with_capacity(payload_len(n) + OVERHEAD);
+    bytes.extend_from_slice(&HEADER);
+    bytes.push(payload_len(n) as u8);
+    bytes.extend_from_slice(&first.device_id.to_be_bytes());
+    bytes.extend_from_slice(&first.ts.timestamp_millis().to_be_bytes());
+    bytes.extend_from_slice(&interval.to_be_bytes());
+    bytes.push(n as u8);
+    for s in samples {
+        bytes.extend_from_slice(&s.x.to_be_bytes());
+        bytes.extend_from_slice(&s.y.to_be_bytes());
+        bytes.extend_from_slice(&s.z.to_be_bytes());
+        bytes.extend_from_slice(&((s.t * 100.0).round() as i16).to_be_bytes());
+        bytes.push(s.bat.round().clamp(0.0, 255.0) as u8);
+    }
+
+    let crc = crc8(&bytes[3..]);
+    bytes.push(crc);
+    Ok(bytes)
+}
+
+pub fn decode(bytes: &[u8], crc: CrcMode) -> Result<AdxlFrame, DecodeError> {
+    if bytes.len() < OVERHEAD {
+        return Err(DecodeError::ShortBuffer {
+            needed: OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+    if bytes[0..2] != HEADER {
+        return Err(DecodeError::BadHeader([bytes[0], bytes[1]]));
+    }
+
+    // check the length
+    let len = bytes[2] as usize;
+    if len + OVERHEAD > bytes.len() {
+        return Err(DecodeError::ShortBuffer {
+            needed: len + OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+    if len + OVERHEAD != bytes.len() {
+        return Err(DecodeError::LengthMismatch {
+            expected: len + OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+    if len < payload_len(0) {
+        return Err(DecodeError::UnsupportedLength(len));
+    }
+    let n = bytes[17] as usize;
+    if n == 0 || n > MAX_SAMPLES {
+        return Err(DecodeError::UnsupportedSampleCount(n));
+    }
+    if len != payload_len(n) {
+        return Err(DecodeError::LengthMismatch {
+            expected: payload_len(n) + OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+
+    let expected = crc8(&bytes[3..3 + len]);
+    let actual = bytes[3 + len];
+    let crc_ok = expected == actual;
+    if !crc_ok {
+        match crc {
+            CrcMode::Strict => return Err(DecodeError::CrcMismatch { expected, actual }),
+            CrcMode::Lenient => warn!(
+                "crc mismatch in ADXL frame: expected 0x{:02X}, got 0x{:02X}",
+                expected, actual
+            ),
+        }
+    }
+
+    let device_id = i32::from_be_bytes(bytes[3..7].try_into().unwrap());
+    let ms = i64::from_be_bytes(bytes[7..15].try_into().unwrap());
+    let ts = Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .ok_or(DecodeError::InvalidTime(ms))?;
+    let interval = u16::from_be_bytes([bytes[15], bytes[16]]);
+
+    let f32_at = |i: usize| f32::from_be_bytes(bytes[i..i + 4].try_into().unwrap());
+    let samples = (0..n)
+        .map(|i| {
+            let at = SAMPLES + i * SAMPLE_LEN;
+            AdxlData {
+                device_id,
+                ts: ts + Duration::milliseconds(i64::from(interval) * i as i64),
+                x: f32_at(at),
+                y: f32_at(at + 4),
+                z: f32_at(at + 8),
+                t: f32::from(i16::from_be_bytes([bytes[at + 12], bytes[at + 13]])) / 100.0,
+                bat: f32::from(bytes[at + 14]),
+            }
+        })
+        .collect();
+
+    Ok(AdxlFrame {
+        device_id,
+        ts,
+        interval,
+        crc_ok,
+        samples,
+    })
+}
@@ -0,0 +1,119 @@
+use bytes::{Buf, BufMut, BytesMut};
+use log::{debug, warn};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::errors::Error;
+use crate::models::humiture_data_v2::HumitureData;
+use crate::protocol::framer::scan;
+use crate::protocol::humiture::{
+    decode, device_id_of, encode, CrcMode, DecodeError, DecodeOptions, Frame,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CodecStats {
+    pub frames: u64,
+    // frames cut out of the stream that did not decode, checksum failures
+    // included
+    pub failed: u64,
+    // bytes dropped while looking for a header
+    pub discarded: u64,
+}
+
+// Humiture frames over a byte stream, for `tokio_util::codec::Framed`.
+//
+// Decoding yields one `Frame` per 0x5A 0xA5 frame, its `samples` are the
+// readings. Garbage between frames and frames that do not decode are skipped
+// and counted, they never end the stream. Encoding takes 1, 12 or 24
+// readings, see `protocol::encode`.
+#[derive(Debug, Default)]
+pub struct HumitureCodec {
+    options: DecodeOptions,
+    // interval code of encoded multi-sample frames
+    interval_code: u8,
+    stats: CodecStats,
+}
+
+impl HumitureCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: DecodeOptions) -> Self {
+        HumitureCodec {
+            options,
+            ..Self::default()
+        }
+    }
+
+    pub fn interval_code(mut self, code: u8) -> Self {
+        self.interval_code = code;
+        self
+    }
+
+    pub fn stats(&self) -> CodecStats {
+        self.stats
+    }
+}
+
+impl Decoder for HumitureCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        loop {
+            // frames are cut by the len byte so that checksum failures are
+            // counted and logged here
+            let (skip, len) = scan(src, CrcMode::Lenient);
+            if skip > 0 {
+                debug!("Discarding {} bytes: {:02X?}", skip, &src[..skip]);
+                src.advance(skip);
+                self.stats.discarded += skip as u64;
+            }
+            let Some(len) = len else {
+                return Ok(None);
+            };
+            let result = decode(&src[..len], &self.options);
+            if let Err(e) = &result {
+                warn!(
+                    "Skipping frame of device 0x{:016X}: {}",
+                    device_id_of(&src[..len]).unwrap_or_default(),
+                    e
+                );
+                self.stats.failed += 1;
+            }
+            match result {
+                Ok(frame) => {
+                    src.advance(len);
+                    self.stats.frames += 1;
+                    return Ok(Some(frame));
+                }
+                // may be a header in garbage, look again one byte further
+                Err(DecodeError::CrcMismatch { .. }) => {
+                    src.advance(1);
+                    self.stats.discarded += 1;
+                }
+                Err(_) => src.advance(len),
+            }
+        }
+    }
+
+    // a frame cut off by the end of the stream is dropped, not an error
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        let frame = self.decode(src)?;
+        if frame.is_none() && !src.is_empty() {
+            debug!("Dropping {} bytes at the end of the stream", src.len());
+            self.stats.discarded += src.len() as u64;
+            src.clear();
+        }
+        Ok(frame)
+    }
+}
+
+impl Encoder<&[HumitureData]> for HumitureCodec {
+    type Error = Error;
+
+    fn encode(&mut self, datas: &[HumitureData], dst: &mut BytesMut) -> Result<(), Error> {
+        dst.put_slice(&encode(datas, self.interval_code)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use log::debug;
+
+use crate::protocol::crc8;
+use crate::protocol::humiture::{sample_count, CrcMode, HEADER, OVERHEAD};
+
+// Look for the next frame at the start of `buf`.
+//
+// Returns the bytes in front of it that are garbage and, once it is complete,
+// the frame length. A header with a len byte of no known layout, or in strict
+// mode a checksum that does not match, is taken as garbage and the search
+// goes on one byte further.
+pub(crate) fn scan(buf: &[u8], crc: CrcMode) -> (usize, Option<usize>) {
+    let mut pos = 0;
+    loop {
+        let Some(start) = buf[pos..]
+            .windows(2)
+            .position(|w| w == HEADER)
+            .map(|i| pos + i)
+        else {
+            // keep a trailing 0x5A, the rest of the header may follow
+            let keep = usize::from(buf.last() == Some(&HEADER[0]));
+            return (buf.len() - keep, None);
+        };
+        let Some(&len) = buf.get(start + 2) else {
+            return (start, None);
+        };
+        let len = len as usize;
+        if sample_count(len).is_none() {
+            pos = start + 1;
+            continue;
+        }
+        let total = len + OVERHEAD;
+        if buf.len() < start + total {
+            return (start, None);
+        }
+        if crc == CrcMode::Strict && crc8(&buf[start + 3..start + 3 + len]) != buf[start + 3 + len]
+        {
+            pos = start + 1;
+            continue;
+        }
+        return (start, Some(total));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FramerStats {
+    pub frames: u64,
+    // bytes dropped while looking for a header
+    pub discarded: u64,
+}
+
+// Cuts 0x5A 0xA5 frames out of a byte stream.
+//
+// Chunks can split or join frames anywhere, `push` them as they are read and
+// take complete frames with `next_frame`. Bytes that are not part of a frame
+// are dropped, so the buffer never holds more than one frame in progress.
+#[derive(Debug, Default)]
+pub struct Framer {
+    buf: Vec<u8>,
+    crc: CrcMode,
+    stats: FramerStats,
+}
+
+impl Framer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // in lenient mode frames are cut by the len byte alone and the checksum
+    // is left to the decoder
+    pub fn with_crc(crc: CrcMode) -> Self {
+        Framer {
+            crc,
+            ..Self::default()
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        let (skip, frame) = scan(&self.buf, self.crc);
+        if skip > 0 {
+            debug!("Discarding {} bytes: {:02X?}", skip, &self.buf[..skip]);
+            self.buf.drain(..skip);
+            self.stats.discarded += skip as u64;
+        }
+        let len = frame?;
+        self.stats.frames += 1;
+        Some(self.buf.drain(..len).collect())
+    }
+
+    // bytes waiting for the rest of a frame
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn stats(&self) -> FramerStats {
+        self.stats
+    }
+}
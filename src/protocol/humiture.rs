@@ -0,0 +1,422 @@
+use std::{error, fmt};
+
+use chrono::{DateTime, Datelike, Duration, Local, TimeZone, Timelike};
+use log::{debug, warn};
+
+use crate::errors::{Error, Result as CrateResult};
+use crate::models::humiture_data_v2::HumitureData;
+use crate::protocol::crc8;
+
+// 0x5A 0xA5 | len | device_id i64 | sn i32 | group | type | YY MM DD hh mm ss
+// | n x temperature i16 | n x humidity i16 | battery | status | crc8
+//
+// `len` counts the bytes from device_id up to the status byte, the crc covers
+// the same bytes. Every value is big endian, temperature / humidity are x10.
+pub const HEADER: [u8; 2] = [0x5A, 0xA5];
+
+// bytes around the payload: header, len and crc
+pub const OVERHEAD: usize = 4;
+
+// offset of the first temperature
+const SAMPLES: usize = 23;
+
+pub const SAMPLE_COUNTS: [usize; 3] = [1, 12, 24];
+
+// value of the len byte for a frame of `n` samples
+pub const fn payload_len(n: usize) -> usize {
+    22 + 4 * n
+}
+
+// samples in a frame with the given len byte: 26 -> 1, 70 -> 12, 118 -> 24
+pub fn sample_count(len: usize) -> Option<usize> {
+    SAMPLE_COUNTS
+        .iter()
+        .copied()
+        .find(|&n| payload_len(n) == len)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // fewer bytes than the frame needs
+    ShortBuffer { needed: usize, actual: usize },
+    BadHeader([u8; 2]),
+    // frame size does not match the len byte or the sample count
+    LengthMismatch { expected: usize, actual: usize },
+    CrcMismatch { expected: u8, actual: u8 },
+    UnsupportedSampleCount(usize),
+    // len byte of no known layout
+    UnsupportedLength(usize),
+    // device time that is no valid local time, in unix milliseconds
+    InvalidTime(i64),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::ShortBuffer { needed, actual } => {
+                write!(f, "short buffer: need {} bytes, got {}", needed, actual)
+            }
+            DecodeError::BadHeader(header) => write!(f, "bad header: {:02X?}", header),
+            DecodeError::LengthMismatch { expected, actual } => write!(
+                f,
+                "length mismatch: expected {} bytes, got {}",
+                expected, actual
+            ),
+            DecodeError::CrcMismatch { expected, actual } => write!(
+                f,
+                "crc mismatch: expected 0x{:02X}, got 0x{:02X}",
+                expected, actual
+            ),
+            DecodeError::UnsupportedSampleCount(n) => write!(f, "unsupported sample count: {}", n),
+            DecodeError::UnsupportedLength(len) => write!(f, "unsupported length: {}", len),
+            DecodeError::InvalidTime(ms) => write!(f, "invalid time: {} ms", ms),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Error::Protocol(error.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrcMode {
+    // frames with a bad checksum are rejected
+    #[default]
+    Strict,
+    // frames with a bad checksum are decoded and flagged
+    Lenient,
+}
+
+// checksum handling and how samples of a frame are timestamped
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeOptions {
+    pub crc: CrcMode,
+    // ignore the device clock and anchor on the receive time
+    pub receive_time: bool,
+    // a device clock further off the receive time than this is not trusted
+    pub max_skew: Option<Duration>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            crc: CrcMode::Strict,
+            receive_time: false,
+            max_skew: Some(Duration::hours(1)),
+        }
+    }
+}
+
+// one decoded frame
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub device_id: i64,
+    pub sn: i32,
+    pub group_id: i32,
+    pub type_id: i32,
+    // frame time the samples are counted back from
+    pub ts: DateTime<Local>,
+    // minutes between two samples
+    pub interval: i32,
+    pub battery: i32,
+    pub people: bool,
+    // raw status byte: bit 0 people, bits 1..3 interval code
+    pub status: i32,
+    // false if the checksum did not match and the frame was decoded anyway
+    pub crc_ok: bool,
+    // samples with out of range values are left out
+    pub samples: Vec<HumitureData>,
+}
+
+// device id of a frame that may not decode, for per device error reports
+pub fn device_id_of(bytes: &[u8]) -> Option<i64> {
+    Some(i64::from_be_bytes(bytes.get(3..11)?.try_into().ok()?))
+}
+
+// YY MM DD hh mm ss at bytes 17..23, local time of the device
+fn device_time(bytes: &[u8]) -> Option<DateTime<Local>> {
+    let [yy, mm, dd, hh, mi, ss] = *bytes.get(17..23)? else {
+        return None;
+    };
+    Local
+        .with_ymd_and_hms(
+            2000 + i32::from(yy),
+            u32::from(mm),
+            u32::from(dd),
+            u32::from(hh),
+            u32::from(mi),
+            u32::from(ss),
+        )
+        .earliest()
+}
+
+// the time samples are counted back from
+fn anchor_time(
+    bytes: &[u8],
+    options: &DecodeOptions,
+    received: DateTime<Local>,
+) -> DateTime<Local> {
+    if options.receive_time {
+        return received;
+    }
+    let Some(ts) = device_time(bytes) else {
+        warn!(
+            "invalid device time {:02X?}, using receive time",
+            bytes.get(17..23)
+        );
+        return received;
+    };
+    if options
+        .max_skew
+        .is_some_and(|max| (ts - received).abs() > max)
+    {
+        warn!("device clock {} is off, using receive time", ts);
+        return received;
+    }
+    ts
+}
+
+// Decode a frame, the sample count follows from the len byte.
+pub fn decode(bytes: &[u8], options: &DecodeOptions) -> Result<Frame, DecodeError> {
+    if bytes.len() < OVERHEAD {
+        return Err(DecodeError::ShortBuffer {
+            needed: OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+    if bytes[0..2] != HEADER {
+        return Err(DecodeError::BadHeader([bytes[0], bytes[1]]));
+    }
+    let len = bytes[2] as usize;
+    let n = sample_count(len).ok_or(DecodeError::UnsupportedLength(len))?;
+    decode_frame(bytes, n, options)
+}
+
+// Decode a frame of `n` samples.
+//
+// A single sample is stamped with the frame time. Multi-sample frames are
+// sent at the end of their period, sample i is (n - i) intervals before it.
+pub fn decode_frame(bytes: &[u8], n: usize, options: &DecodeOptions) -> Result<Frame, DecodeError> {
+    if !SAMPLE_COUNTS.contains(&n) {
+        return Err(DecodeError::UnsupportedSampleCount(n));
+    }
+    if bytes.len() < OVERHEAD {
+        return Err(DecodeError::ShortBuffer {
+            needed: OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+    if bytes[0..2] != HEADER {
+        return Err(DecodeError::BadHeader([bytes[0], bytes[1]]));
+    }
+
+    // check the length
+    let len = bytes[2] as usize;
+    if len + OVERHEAD > bytes.len() {
+        return Err(DecodeError::ShortBuffer {
+            needed: len + OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+    if len + OVERHEAD != bytes.len() {
+        return Err(DecodeError::LengthMismatch {
+            expected: len + OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+    if len != payload_len(n) {
+        return Err(DecodeError::LengthMismatch {
+            expected: payload_len(n) + OVERHEAD,
+            actual: bytes.len(),
+        });
+    }
+
+    let expected = crc8(&bytes[3..3 + len]);
+    let actual = bytes[3 + len];
+    let crc_ok = expected == actual;
+    if !crc_ok {
+        match options.crc {
+            CrcMode::Strict => return Err(DecodeError::CrcMismatch { expected, actual }),
+            CrcMode::Lenient => warn!(
+                "crc mismatch in frame of device 0x{:016X}: expected 0x{:02X}, got 0x{:02X}",
+                device_id_of(bytes).unwrap_or_default(),
+                expected,
+                actual
+            ),
+        }
+    }
+
+    let device_id = i64::from_be_bytes(bytes[3..11].try_into().unwrap());
+    let sn = i32::from_be_bytes(bytes[11..15].try_into().unwrap());
+    let group_id = i32::from(bytes[15]);
+    let type_id = i32::from(bytes[16]);
+
+    // 000 -> 5min
+    // 001 -> 10min
+    // .........
+    // 111 -> 40min
+    let battery = i32::from(bytes[SAMPLES + 4 * n]);
+    let status = bytes[SAMPLES + 4 * n + 1];
+    let people = status & 0x01 != 0;
+    let interval = interval_minutes((status >> 1) & 0x07);
+
+    let ts = anchor_time(bytes, options, Local::now());
+
+    let mut samples = Vec::with_capacity(n);
+    for i in 0..n {
+        let tt = i16::from_be_bytes([bytes[SAMPLES + i * 2], bytes[SAMPLES + 1 + i * 2]]);
+        let hh = i16::from_be_bytes([
+            bytes[SAMPLES + i * 2 + 2 * n],
+            bytes[SAMPLES + 1 + i * 2 + 2 * n],
+        ]);
+        let t = f32::from(tt) / 10.0;
+        let h = f32::from(hh) / 10.0;
+
+        // seperate the time(1h/2h/4h) into n slices
+        let sample_ts = if n == 1 {
+            ts
+        } else {
+            ts - Duration::minutes(i64::from(interval) * (n - i) as i64)
+        };
+
+        let new_data = HumitureData {
+            sn,
+            ts: sample_ts,
+            device_id,
+            group_id,
+            type_id,
+            temperature: t,
+            humidity: h,
+            battery,
+            people,
+            status: i32::from(status),
+        };
+
+        // temperature and humidity check, test data (group 0, type 0) passes
+        let in_range = (-40.0..=100.0).contains(&t) && (0.0..=100.0).contains(&h);
+        if in_range || (group_id == 0 && type_id == 0) {
+            debug!("{}", new_data);
+            samples.push(new_data);
+        } else {
+            warn!("{} --- Overflow!", new_data)
+        }
+    }
+
+    Ok(Frame {
+        device_id,
+        sn,
+        group_id,
+        type_id,
+        ts,
+        interval,
+        battery,
+        people,
+        status: i32::from(status),
+        crc_ok,
+        samples,
+    })
+}
+
+// minutes between two samples for an interval code, 0 -> 5 ... 7 -> 40
+pub const fn interval_minutes(code: u8) -> i32 {
+    (code as i32 + 1) * 5
+}
+
+// YY MM DD hh mm ss of the device clock, seconds are truncated
+fn push_time(bytes: &mut Vec<u8>, ts: &DateTime<Local>) -> CrateResult<()> {
+    let year = u8::try_from(ts.year() - 2000)
+        .map_err(|_| Error::validation(format!("year out of range: {}", ts.year())))?;
+    bytes.extend_from_slice(&[
+        year,
+        ts.month() as u8,
+        ts.day() as u8,
+        ts.hour() as u8,
+        ts.minute() as u8,
+        ts.second() as u8,
+    ]);
+    Ok(())
+}
+
+// Encode readings of one device into a frame of 1, 12 or 24 samples.
+//
+// Readings are oldest first and `interval_code` (0..=7) minutes apart. The
+// header, battery and status come from the first reading, the frame time is
+// one interval after the last reading, the way `decode_frame` counts back.
+// A single reading is stamped with its own time.
+pub fn encode(readings: &[HumitureData], interval_code: u8) -> CrateResult<Vec<u8>> {
+    let n = readings.len();
+    if !SAMPLE_COUNTS.contains(&n) {
+        return Err(DecodeError::UnsupportedSampleCount(n).into());
+    }
+    if interval_code > 0x07 {
+        return Err(Error::validation(format!(
+            "interval code out of range: {}",
+            interval_code
+        )));
+    }
+    let first = &readings[0];
+    let interval = Duration::minutes(i64::from(interval_minutes(interval_code)));
+    if let Some(other) = readings.iter().find(|r| r.device_id != first.device_id) {
+        return Err(Error::validation(format!(
+            "readings of devices 0x{:016X} and 0x{:016X} in one frame",
+            first.device_id, other.device_id
+        )));
+    }
+    if n > 1 && readings.windows(2).any(|w| w[1].ts - w[0].ts != interval) {
+        return Err(Error::validation(format!(
+            "readings are not {} minutes apart",
+            interval.num_minutes()
+        )));
+    }
+
+    let mut bytes = Vec::with_capacity(payload_len(n) + OVERHEAD);
+    bytes.extend_from_slice(&HEADER);
+    bytes.push(payload_len(n) as u8);
+    bytes.extend_from_slice(&first.device_id.to_be_bytes());
+    bytes.extend_from_slice(&first.sn.to_be_bytes());
+    bytes.push(first.group_id as u8);
+    bytes.push(first.type_id as u8);
+
+    let ts = if n == 1 {
+        first.ts
+    } else {
+        readings[n - 1].ts + interval
+    };
+    push_time(&mut bytes, &ts)?;
+
+    for r in readings {
+        bytes.extend_from_slice(&((r.temperature * 10.0).round() as i16).to_be_bytes());
+    }
+    for r in readings {
+        bytes.extend_from_slice(&((r.humidity * 10.0).round() as i16).to_be_bytes());
+    }
+
+    // bits above the interval code are passed through
+    bytes.push(first.battery as u8);
+    bytes.push((first.status as u8 & !0x0F) | (interval_code << 1) | u8::from(first.people));
+
+    let crc = crc8(&bytes[3..]);
+    bytes.push(crc);
+    Ok(bytes)
+}
+
+// value of the len byte of an ACK, no data frame has it
+pub const ACK_LEN: usize = 13;
+
+// 0x5A 0xA5 | 13 | device_id i64 | sn i32 | result | crc8, result 0 is
+// stored, anything else asks the device to send the frame again
+pub fn encode_ack(device_id: i64, sn: i32, stored: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(ACK_LEN + OVERHEAD);
+    bytes.extend_from_slice(&HEADER);
+    bytes.push(ACK_LEN as u8);
+    bytes.extend_from_slice(&device_id.to_be_bytes());
+    bytes.extend_from_slice(&sn.to_be_bytes());
+    bytes.push(u8::from(!stored));
+    let crc = crc8(&bytes[3..]);
+    bytes.push(crc);
+    bytes
+}
@@ -0,0 +1,18 @@
+use crc::{Crc, CRC_8_MAXIM_DOW};
+
+pub mod adxl;
+pub mod codec;
+pub mod framer;
+pub mod humiture;
+
+pub use adxl::AdxlFrame;
+pub use codec::{CodecStats, HumitureCodec};
+pub use framer::{Framer, FramerStats};
+pub use humiture::{decode, decode_frame, encode, CrcMode, DecodeError, DecodeOptions, Frame};
+
+const CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+
+// CRC-8/MAXIM, as appended by the devices
+pub fn crc8(bytes: &[u8]) -> u8 {
+    CRC8.checksum(bytes)
+}
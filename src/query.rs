@@ -0,0 +1,444 @@
+use std::fmt::Write;
+
+use crate::errors::{Error, Result};
+use crate::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ge,
+    Le,
+}
+
+impl Op {
+    fn as_sql(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ge => ">=",
+            Op::Le => "<=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Int(i64),
+    Str(String),
+}
+
+impl From<i32> for SqlValue {
+    fn from(v: i32) -> Self {
+        SqlValue::Int(v.into())
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(v: i64) -> Self {
+        SqlValue::Int(v)
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(v: &str) -> Self {
+        SqlValue::Str(v.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(v: String) -> Self {
+        SqlValue::Str(v)
+    }
+}
+
+// quoted string literal, backslashes and quotes escaped
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('\'');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '"' => out.push_str("\\\""),
+            _ => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+// database, table and column names: [A-Za-z_][A-Za-z0-9_]*
+pub fn check_ident(name: &str) -> Result<&str> {
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+    if valid {
+        Ok(name)
+    } else {
+        Err(Error::validation(format!("invalid identifier: {:?}", name)))
+    }
+}
+
+// SELECT * FROM <table> [WHERE ...] ORDER BY ts <order> [LIMIT n] [OFFSET n]
+//
+// Identifiers are checked, string values are quoted and escaped and numbers
+// are rendered from typed values, so caller input never becomes raw SQL.
+#[derive(Debug, Clone)]
+pub struct Select {
+    table: String,
+    conds: Vec<String>,
+    order: Order,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl Select {
+    // `table` may be qualified as `db.table`
+    pub fn from(table: &str) -> Result<Self> {
+        for part in table.split('.') {
+            check_ident(part)?;
+        }
+        Ok(Select {
+            table: table.to_string(),
+            conds: Vec::new(),
+            order: Order::default(),
+            limit: None,
+            offset: None,
+        })
+    }
+
+    pub fn filter(mut self, column: &str, op: Op, value: impl Into<SqlValue>) -> Result<Self> {
+        let value = match value.into() {
+            SqlValue::Int(v) => v.to_string(),
+            SqlValue::Str(v) => quote(&v),
+        };
+        self.conds
+            .push(format!("{}{}{}", check_ident(column)?, op.as_sql(), value));
+        Ok(self)
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn offset(mut self, offset: Option<usize>) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn to_sql(&self) -> String {
+        let mut sql = format!("SELECT * FROM {}", self.table);
+        if !self.conds.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&self.conds.join(" AND "));
+        }
+        sql.push_str(match self.order {
+            Order::Asc => " ORDER BY ts ASC",
+            Order::Desc => " ORDER BY ts DESC",
+        });
+        // TDengine only takes OFFSET after a LIMIT, like sqlite
+        match (self.limit, self.offset) {
+            (Some(limit), _) => {
+                let _ = write!(sql, " LIMIT {}", limit);
+            }
+            (None, Some(_)) => {
+                let _ = write!(sql, " LIMIT {}", i64::MAX);
+            }
+            (None, None) => {}
+        }
+        if let Some(offset) = self.offset {
+            let _ = write!(sql, " OFFSET {}", offset);
+        }
+        sql
+    }
+}
+
+// optional filters shared by both queries
+fn push_common(
+    mut select: Select,
+    start: Option<i64>,
+    end: Option<i64>,
+    tags: &[(String, String)],
+) -> Result<Select> {
+    if let Some(start) = start {
+        select = select.filter("ts", Op::Ge, start)?;
+    }
+    if let Some(end) = end {
+        select = select.filter("ts", Op::Le, end)?;
+    }
+    for (name, value) in tags {
+        select = select.filter(name, Op::Eq, value.as_str())?;
+    }
+    Ok(select)
+}
+
+// Filter for humiture readings, newest first unless ordered otherwise.
+// Timestamps are unix milliseconds, `start` and `end` are inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct HumitureQuery {
+    pub device_id: Option<i64>,
+    pub sn: Option<i32>,
+    pub group_id: Option<i32>,
+    pub type_id: Option<i32>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    // string equality filters on tag columns, e.g. ("site", "north-3")
+    pub tags: Vec<(String, String)>,
+    pub order: Order,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl HumitureQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn device(mut self, device_id: i64) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    pub fn sn(mut self, sn: i32) -> Self {
+        self.sn = Some(sn);
+        self
+    }
+
+    pub fn group(mut self, group_id: i32) -> Self {
+        self.group_id = Some(group_id);
+        self
+    }
+
+    pub fn type_id(mut self, type_id: i32) -> Self {
+        self.type_id = Some(type_id);
+        self
+    }
+
+    pub fn between(mut self, start: i64, end: i64) -> Self {
+        self.start = Some(start);
+        self.end = Some(end);
+        self
+    }
+
+    pub fn tag(mut self, name: &str, value: &str) -> Self {
+        self.tags.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn to_select(&self, table: &str) -> Result<Select> {
+        let mut select = Select::from(table)?;
+        if let Some(device_id) = self.device_id {
+            select = select.filter("device_id", Op::Eq, device_id)?;
+        }
+        if let Some(sn) = self.sn {
+            select = select.filter("sn", Op::Eq, sn)?;
+        }
+        if let Some(group_id) = self.group_id {
+            select = select.filter("group_id", Op::Eq, group_id)?;
+        }
+        if let Some(type_id) = self.type_id {
+            select = select.filter("type_id", Op::Eq, type_id)?;
+        }
+        Ok(push_common(select, self.start, self.end, &self.tags)?
+            .order(self.order)
+            .limit(self.limit)
+            .offset(self.offset))
+    }
+
+    // check a single record against the filters (tags, order, limit and
+    // offset are not applied here)
+    pub fn matches(&self, data: &HumitureData) -> bool {
+        let ts = data.ts.timestamp_millis();
+        self.device_id.is_none_or(|v| v == data.device_id)
+            && self.sn.is_none_or(|v| v == data.sn)
+            && self.group_id.is_none_or(|v| v == data.group_id)
+            && self.type_id.is_none_or(|v| v == data.type_id)
+            && self.start.is_none_or(|v| ts >= v)
+            && self.end.is_none_or(|v| ts <= v)
+    }
+}
+
+// Filter for ADXL samples, newest first unless ordered otherwise.
+// Timestamps are unix milliseconds, `start` and `end` are inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct AdxlQuery {
+    pub device_id: Option<i32>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    // string equality filters on tag columns, e.g. ("site", "north-3")
+    pub tags: Vec<(String, String)>,
+    pub order: Order,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl AdxlQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn device(mut self, device_id: i32) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    pub fn between(mut self, start: i64, end: i64) -> Self {
+        self.start = Some(start);
+        self.end = Some(end);
+        self
+    }
+
+    pub fn tag(mut self, name: &str, value: &str) -> Self {
+        self.tags.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn to_select(&self, table: &str) -> Result<Select> {
+        let mut select = Select::from(table)?;
+        if let Some(device_id) = self.device_id {
+            select = select.filter("device_id", Op::Eq, device_id)?;
+        }
+        Ok(push_common(select, self.start, self.end, &self.tags)?
+            .order(self.order)
+            .limit(self.limit)
+            .offset(self.offset))
+    }
+
+    // check a single record against the filters (tags, order, limit and
+    // offset are not applied here)
+    pub fn matches(&self, data: &AdxlData) -> bool {
+        let ts = data.ts.timestamp_millis();
+        self.device_id.is_none_or(|v| v == data.device_id)
+            && self.start.is_none_or(|v| ts >= v)
+            && self.end.is_none_or(|v| ts <= v)
+    }
+}
+
+// tag columns only exist in the TDengine super tables
+pub(crate) fn check_no_tags(tags: &[(String, String)]) -> Result<()> {
+    match tags.first() {
+        None => Ok(()),
+        Some((name, _)) => Err(Error::validation(format!(
+            "tag filter {:?} is not supported by this backend",
+            name
+        ))),
+    }
+}
+
+// the legacy query functions take an i32 limit
+pub(crate) fn check_limit(limit: i32) -> Result<usize> {
+    usize::try_from(limit).map_err(|_| Error::validation(format!("negative limit: {}", limit)))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{AdxlQuery, HumitureQuery, Op, Order, Select};
+
+    #[test]
+    fn test_humiture_sql() {
+        let sql = HumitureQuery::new()
+            .device(0x0000111122223333)
+            .type_id(2)
+            .between(1000, 2000)
+            .limit(10)
+            .offset(20)
+            .to_select("humiture.humiture")
+            .unwrap()
+            .to_sql();
+        assert_eq!(
+            sql,
+            "SELECT * FROM humiture.humiture WHERE device_id=18765284782899 AND type_id=2 \
+             AND ts>=1000 AND ts<=2000 ORDER BY ts DESC LIMIT 10 OFFSET 20"
+        );
+
+        let sql = AdxlQuery::new()
+            .order(Order::Asc)
+            .to_select("adxl.adxl355")
+            .unwrap()
+            .to_sql();
+        assert_eq!(sql, "SELECT * FROM adxl.adxl355 ORDER BY ts ASC");
+
+        // no OFFSET without a LIMIT
+        let sql = HumitureQuery::new()
+            .offset(5)
+            .to_select("humiture.humiture")
+            .unwrap()
+            .to_sql();
+        assert_eq!(
+            sql,
+            "SELECT * FROM humiture.humiture ORDER BY ts DESC LIMIT 9223372036854775807 OFFSET 5"
+        );
+    }
+
+    #[test]
+    fn test_injection() {
+        // string values are quoted and escaped
+        let sql = HumitureQuery::new()
+            .tag("site", "x' OR '1'='1")
+            .to_select("humiture.humiture")
+            .unwrap()
+            .to_sql();
+        assert_eq!(
+            sql,
+            "SELECT * FROM humiture.humiture WHERE site='x\\' OR \\'1\\'=\\'1' ORDER BY ts DESC"
+        );
+
+        // identifiers are never quoted, so they are rejected instead
+        assert!(HumitureQuery::new()
+            .tag("site=1 OR 1", "x")
+            .to_select("humiture.humiture")
+            .is_err());
+        assert!(Select::from("humiture; DROP DATABASE humiture").is_err());
+        assert!(Select::from("humiture.humiture")
+            .unwrap()
+            .filter("ts;", Op::Eq, 1)
+            .is_err());
+    }
+}
@@ -35,6 +35,9 @@ diesel::table! {
         ts -> Timestamp,
         temperature -> Float4,
         humidity -> Float4,
+        battery -> Int4,
+        people -> Bool,
+        status -> Int4,
     }
 }
 
@@ -0,0 +1,103 @@
+// Humiture frames from a USB-serial radio dongle, see `bin/serial_ingest.rs`.
+use std::io::{self, Read};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use serial::SerialPort;
+use tokio::sync::mpsc;
+
+use crate::protocol::{decode, DecodeOptions, Framer};
+use crate::store::ReadingSender;
+use crate::tdengine::Backoff;
+
+// blocking, sends what it reads until the port fails or the receiver is gone
+pub fn read_port(name: &str, baud: usize, tx: mpsc::Sender<Vec<u8>>) -> io::Result<()> {
+    let mut port = serial::open(name)?;
+    port.reconfigure(&|settings| {
+        settings.set_baud_rate(serial::BaudRate::from_speed(baud))?;
+        settings.set_char_size(serial::Bits8);
+        settings.set_parity(serial::ParityNone);
+        settings.set_stop_bits(serial::Stop1);
+        settings.set_flow_control(serial::FlowNone);
+        Ok(())
+    })?;
+    // wake up now and then to notice a shutdown
+    port.set_timeout(Duration::from_secs(1))?;
+    info!("Opened {} at {} baud", name, baud);
+
+    let mut buf = [0u8; 512];
+    loop {
+        match port.read(&mut buf) {
+            // hang up
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                if tx.blocking_send(buf[..n].to_vec()).is_err() {
+                    return Ok(());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                if tx.is_closed() {
+                    return Ok(());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// returns once the writer is closed
+pub async fn ingest(name: &str, baud: usize, sender: ReadingSender) {
+    let options = DecodeOptions::default();
+    let backoff = Backoff {
+        max: Duration::from_secs(30),
+        ..Backoff::default()
+    };
+    let mut attempt = 0;
+
+    loop {
+        let (tx, mut rx) = mpsc::channel(64);
+        let port = name.to_string();
+        let reader = tokio::task::spawn_blocking(move || read_port(&port, baud, tx));
+
+        let mut framer = Framer::new();
+        while let Some(chunk) = rx.recv().await {
+            framer.push(&chunk);
+            while let Some(bytes) = framer.next_frame() {
+                let frame = match decode(&bytes, &options) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        warn!("Bad frame {:02X?}: {}", bytes, e);
+                        continue;
+                    }
+                };
+                debug!(
+                    "Frame of device 0x{:016X}, {} samples",
+                    frame.device_id,
+                    frame.samples.len()
+                );
+                attempt = 0;
+                for sample in frame.samples {
+                    if sender.write(sample).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        let stats = framer.stats();
+        info!(
+            "{} closed after {} frames, {} bytes discarded",
+            name, stats.frames, stats.discarded
+        );
+
+        match reader.await {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => warn!("{}: {}", name, e),
+            Err(e) => warn!("{} reader failed: {}", name, e),
+        }
+        let delay = backoff.delay(attempt);
+        info!("Reopening {} in {:?}", name, delay);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
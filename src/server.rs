@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::future::{pending, Future};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio_util::codec::Framed;
+
+use crate::errors::Result;
+use crate::protocol::humiture::encode_ack;
+use crate::protocol::{DecodeOptions, HumitureCodec};
+use crate::store::SensorStore;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ServerConfig {
+    pub options: DecodeOptions,
+    // answer every frame with an ACK once its readings are stored
+    pub ack: bool,
+    // drop connections silent for this long
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            options: DecodeOptions::default(),
+            ack: false,
+            idle_timeout: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub peer: SocketAddr,
+    pub connected: DateTime<Local>,
+    pub frames: u64,
+    pub readings: u64,
+    // frames that did not decode
+    pub failed: u64,
+    // bytes dropped while looking for a header
+    pub discarded: u64,
+    // frames whose readings the store did not take
+    pub store_errors: u64,
+}
+
+impl ConnectionStats {
+    fn new(peer: SocketAddr) -> Self {
+        ConnectionStats {
+            peer,
+            connected: Local::now(),
+            frames: 0,
+            readings: 0,
+            failed: 0,
+            discarded: 0,
+            store_errors: 0,
+        }
+    }
+}
+
+// TCP server for gateways pushing raw humiture frames.
+//
+// Every connection is read through a `HumitureCodec` on its own task, the
+// readings of each frame are written to the store as one batch. With `ack`
+// on, the frame is answered once the write returned, see `encode_ack`.
+pub struct TcpServer {
+    listener: TcpListener,
+    store: Arc<dyn SensorStore>,
+    config: ServerConfig,
+    next_id: AtomicU64,
+    connections: Arc<Mutex<HashMap<u64, ConnectionStats>>>,
+}
+
+impl TcpServer {
+    pub async fn bind<A: ToSocketAddrs>(
+        addr: A,
+        store: Arc<dyn SensorStore>,
+        config: ServerConfig,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(TcpServer {
+            listener,
+            store,
+            config,
+            next_id: AtomicU64::new(0),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // stats of the open connections
+    pub fn connections(&self) -> Vec<ConnectionStats> {
+        let connections = self.connections.lock().unwrap();
+        let mut stats: Vec<_> = connections.values().copied().collect();
+        stats.sort_by_key(|s| s.connected);
+        stats
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        self.run_until(pending()).await
+    }
+
+    // accept connections until `shutdown` completes, open connections are
+    // left to finish on their own
+    pub async fn run_until<F: Future<Output = ()>>(&self, shutdown: F) -> Result<()> {
+        info!("Listening on {}", self.local_addr()?);
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, peer)) => self.spawn(stream, peer),
+                    // e.g. out of file descriptors, keep serving the others
+                    Err(e) => {
+                        error!("Accept failed: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                },
+                _ = &mut shutdown => return Ok(()),
+            }
+        }
+    }
+
+    fn spawn(&self, stream: TcpStream, peer: SocketAddr) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(id, ConnectionStats::new(peer));
+        info!("{} connected", peer);
+
+        let store = self.store.clone();
+        let config = self.config;
+        let connections = self.connections.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, id, store.as_ref(), &config, &connections).await {
+                warn!("{}: {}", peer, e);
+            }
+            if let Some(stats) = connections.lock().unwrap().remove(&id) {
+                info!("{} disconnected: {:?}", peer, stats);
+            }
+        });
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    id: u64,
+    store: &dyn SensorStore,
+    config: &ServerConfig,
+    connections: &Mutex<HashMap<u64, ConnectionStats>>,
+) -> Result<()> {
+    let mut framed = Framed::new(stream, HumitureCodec::with_options(config.options));
+
+    loop {
+        let next = match config.idle_timeout {
+            Some(idle) => match tokio::time::timeout(idle, framed.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    debug!("Closing idle connection");
+                    return Ok(());
+                }
+            },
+            None => framed.next().await,
+        };
+        let Some(frame) = next.transpose()? else {
+            return Ok(());
+        };
+
+        let stored = match store.insert_humiture_batch(&frame.samples).await {
+            Ok(_) => true,
+            Err(e) => {
+                error!(
+                    "Failed to write {} readings of device 0x{:016X}: {}",
+                    frame.samples.len(),
+                    frame.device_id,
+                    e
+                );
+                false
+            }
+        };
+
+        if let Some(stats) = connections.lock().unwrap().get_mut(&id) {
+            let codec = framed.codec().stats();
+            stats.frames = codec.frames;
+            stats.failed = codec.failed;
+            stats.discarded = codec.discarded;
+            if stored {
+                stats.readings += frame.samples.len() as u64;
+            } else {
+                stats.store_errors += 1;
+            }
+        }
+
+        if config.ack {
+            let ack = encode_ack(frame.device_id, frame.sn, stored);
+            framed.get_mut().write_all(&ack).await?;
+        }
+    }
+}
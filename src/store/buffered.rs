@@ -0,0 +1,188 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+use crate::errors::{Error, Result};
+use crate::models::reading::Reading;
+use crate::store::SensorStore;
+
+// when the background task writes a batch
+#[derive(Debug, Clone, Copy)]
+pub struct BufferConfig {
+    // flush as soon as this many readings are buffered
+    pub max_rows: usize,
+    // flush at the latest this long after the first buffered reading
+    pub max_delay: Duration,
+    // readings the channel holds before `write` starts waiting
+    pub capacity: usize,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        BufferConfig {
+            max_rows: 500,
+            max_delay: Duration::from_secs(1),
+            capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriterStats {
+    pub flushes: u64,
+    pub rows: u64,
+    // readings dropped because the store returned an error
+    pub failed: u64,
+}
+
+// Cheap handle to queue readings, clone one into every receive task.
+#[derive(Clone)]
+pub struct ReadingSender {
+    tx: mpsc::Sender<Reading>,
+}
+
+impl ReadingSender {
+    // waits while the channel is full
+    pub async fn write(&self, reading: impl Into<Reading>) -> Result<()> {
+        self.tx
+            .send(reading.into())
+            .await
+            .map_err(|_| Error::connection("buffered writer is closed"))
+    }
+}
+
+// Background writer decoupling the receive path from the store.
+//
+// Readings go through a bounded channel into a task that buffers them and
+// writes one batch per kind when `max_rows` or `max_delay` is reached. A full
+// channel makes `write` wait, so a slow store slows the producers down
+// instead of growing memory. `shutdown` flushes whatever is left.
+pub struct BufferedWriter {
+    sender: ReadingSender,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<WriterStats>,
+}
+
+impl BufferedWriter {
+    pub fn spawn<S>(store: Arc<S>, config: BufferConfig) -> Self
+    where
+        S: SensorStore + ?Sized + 'static,
+    {
+        let (tx, rx) = mpsc::channel(config.capacity.max(1));
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let task = tokio::spawn(run(store, config, rx, shutdown_rx));
+        BufferedWriter {
+            sender: ReadingSender { tx },
+            shutdown,
+            task,
+        }
+    }
+
+    pub fn sender(&self) -> ReadingSender {
+        self.sender.clone()
+    }
+
+    pub async fn write(&self, reading: impl Into<Reading>) -> Result<()> {
+        self.sender.write(reading).await
+    }
+
+    // stop accepting readings, flush the buffer and wait for the task
+    pub async fn shutdown(self) -> Result<WriterStats> {
+        let _ = self.shutdown.send(());
+        drop(self.sender);
+        Ok(self.task.await?)
+    }
+}
+
+async fn run<S>(
+    store: Arc<S>,
+    config: BufferConfig,
+    mut rx: mpsc::Receiver<Reading>,
+    mut shutdown: oneshot::Receiver<()>,
+) -> WriterStats
+where
+    S: SensorStore + ?Sized,
+{
+    let mut stats = WriterStats::default();
+    let mut buffer = Vec::with_capacity(config.max_rows);
+    let mut deadline: Option<Instant> = None;
+    let mut closing = false;
+
+    loop {
+        tokio::select! {
+            reading = rx.recv() => match reading {
+                Some(reading) => {
+                    if buffer.is_empty() {
+                        deadline = Some(Instant::now() + config.max_delay);
+                    }
+                    buffer.push(reading);
+                    if buffer.len() >= config.max_rows {
+                        flush(store.as_ref(), &mut buffer, &mut stats).await;
+                        deadline = None;
+                    }
+                }
+                // closed and drained
+                None => break,
+            },
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                flush(store.as_ref(), &mut buffer, &mut stats).await;
+                deadline = None;
+            }
+            _ = &mut shutdown, if !closing => {
+                // senders still alive get an error, queued readings are kept
+                closing = true;
+                rx.close();
+            }
+        }
+    }
+
+    flush(store.as_ref(), &mut buffer, &mut stats).await;
+    debug!("Buffered writer stopped: {:?}", stats);
+    stats
+}
+
+async fn flush<S>(store: &S, buffer: &mut Vec<Reading>, stats: &mut WriterStats)
+where
+    S: SensorStore + ?Sized,
+{
+    if buffer.is_empty() {
+        return;
+    }
+
+    let mut humitures = Vec::new();
+    let mut adxls = Vec::new();
+    for reading in buffer.drain(..) {
+        match reading {
+            Reading::Humiture(data) => humitures.push(data),
+            Reading::Adxl(data) => adxls.push(data),
+        }
+    }
+
+    stats.flushes += 1;
+    if !humitures.is_empty() {
+        match store.insert_humiture_batch(&humitures).await {
+            Ok(_) => stats.rows += humitures.len() as u64,
+            Err(e) => {
+                error!(
+                    "Failed to write {} humiture readings: {}",
+                    humitures.len(),
+                    e
+                );
+                stats.failed += humitures.len() as u64;
+            }
+        }
+    }
+    if !adxls.is_empty() {
+        match store.insert_adxl_batch(&adxls).await {
+            Ok(_) => stats.rows += adxls.len() as u64,
+            Err(e) => {
+                error!("Failed to write {} ADXL samples: {}", adxls.len(), e);
+                stats.failed += adxls.len() as u64;
+            }
+        }
+    }
+}
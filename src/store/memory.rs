@@ -0,0 +1,96 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::errors::Result;
+use crate::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+use crate::query::{check_no_tags, AdxlQuery, HumitureQuery, Order};
+use crate::store::SensorStore;
+
+// In-memory backend for tests and offline development, nothing is persisted.
+// Queries follow the TDengine semantics: ORDER BY ts, then LIMIT / OFFSET.
+#[derive(Default)]
+pub struct MemoryStore {
+    humiture: Mutex<Vec<HumitureData>>,
+    adxl: Mutex<Vec<AdxlData>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// filter, sort by ts and apply offset and limit
+fn select<T: Clone>(
+    rows: &[T],
+    filter: impl Fn(&T) -> bool,
+    ts: impl Fn(&T) -> i64,
+    order: Order,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Vec<T> {
+    let mut records: Vec<T> = rows.iter().filter(|r| filter(r)).cloned().collect();
+    match order {
+        Order::Asc => records.sort_by_key(|r| ts(r)),
+        Order::Desc => records.sort_by_key(|r| std::cmp::Reverse(ts(r))),
+    }
+    records
+        .into_iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+#[async_trait]
+impl SensorStore for MemoryStore {
+    async fn init(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn insert_humiture(&self, data: &HumitureData) -> Result<usize> {
+        self.humiture.lock().unwrap().push(data.clone());
+        Ok(1)
+    }
+
+    async fn insert_humiture_batch(&self, datas: &[HumitureData]) -> Result<usize> {
+        self.humiture.lock().unwrap().extend_from_slice(datas);
+        Ok(datas.len())
+    }
+
+    async fn query_humiture(&self, query: &HumitureQuery) -> Result<Vec<HumitureData>> {
+        check_no_tags(&query.tags)?;
+        let rows = self.humiture.lock().unwrap();
+        Ok(select(
+            &rows,
+            |r| query.matches(r),
+            |r| r.ts.timestamp_millis(),
+            query.order,
+            query.limit,
+            query.offset,
+        ))
+    }
+
+    async fn insert_adxl(&self, data: &AdxlData) -> Result<usize> {
+        self.adxl.lock().unwrap().push(data.clone());
+        Ok(1)
+    }
+
+    async fn insert_adxl_batch(&self, datas: &[AdxlData]) -> Result<usize> {
+        self.adxl.lock().unwrap().extend_from_slice(datas);
+        Ok(datas.len())
+    }
+
+    async fn query_adxl(&self, query: &AdxlQuery) -> Result<Vec<AdxlData>> {
+        check_no_tags(&query.tags)?;
+        let rows = self.adxl.lock().unwrap();
+        Ok(select(
+            &rows,
+            |r| query.matches(r),
+            |r| r.ts.timestamp_millis(),
+            query.order,
+            query.limit,
+            query.offset,
+        ))
+    }
+}
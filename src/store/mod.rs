@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::errors::{Error, Result};
+use crate::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+use crate::query::{AdxlQuery, HumitureQuery};
+
+pub mod buffered;
+pub mod memory;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod spool;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod tdengine;
+
+pub use buffered::{BufferConfig, BufferedWriter, ReadingSender, WriterStats};
+pub use memory::MemoryStore;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+pub use spool::{Spool, SpoolingStore};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+pub use tdengine::{AdxlWriter, HumitureWriter, TdTable, TdengineStore};
+
+// Storage backend for humiture and ADXL readings.
+//
+// Callers program against this trait and pick a backend at startup, e.g.
+// `let store: Box<dyn SensorStore> = Box::new(TdengineStore::connect(url).await?);`
+#[async_trait]
+pub trait SensorStore: Send + Sync {
+    // create databases / tables if they do not exist yet
+    async fn init(&self) -> Result<()>;
+
+    async fn insert_humiture(&self, data: &HumitureData) -> Result<usize>;
+
+    async fn insert_humiture_batch(&self, datas: &[HumitureData]) -> Result<usize> {
+        let mut rows = 0;
+        for data in datas {
+            rows += self.insert_humiture(data).await?;
+        }
+        Ok(rows)
+    }
+
+    async fn query_humiture(&self, query: &HumitureQuery) -> Result<Vec<HumitureData>>;
+
+    // readings of one device between two unix millisecond timestamps, newest first
+    async fn query_humiture_range(
+        &self,
+        device_id: i64,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<HumitureData>> {
+        let query = HumitureQuery::new().device(device_id).between(start, end);
+        self.query_humiture(&query).await
+    }
+
+    // latest `limit` readings of one device, newest first
+    async fn query_humiture_latest(
+        &self,
+        device_id: i64,
+        limit: usize,
+    ) -> Result<Vec<HumitureData>> {
+        let query = HumitureQuery::new().device(device_id).limit(limit);
+        self.query_humiture(&query).await
+    }
+
+    // latest `limit` readings with the given serial number, newest first
+    async fn query_humiture_by_sn(&self, sn: i32, limit: usize) -> Result<Vec<HumitureData>> {
+        let query = HumitureQuery::new().sn(sn).limit(limit);
+        self.query_humiture(&query).await
+    }
+
+    // latest `limit` readings of a group, newest first
+    async fn query_humiture_by_group(
+        &self,
+        group_id: i32,
+        limit: usize,
+    ) -> Result<Vec<HumitureData>> {
+        let query = HumitureQuery::new().group(group_id).limit(limit);
+        self.query_humiture(&query).await
+    }
+
+    async fn insert_adxl(&self, data: &AdxlData) -> Result<usize>;
+
+    async fn insert_adxl_batch(&self, datas: &[AdxlData]) -> Result<usize> {
+        let mut rows = 0;
+        for data in datas {
+            rows += self.insert_adxl(data).await?;
+        }
+        Ok(rows)
+    }
+
+    async fn query_adxl(&self, query: &AdxlQuery) -> Result<Vec<AdxlData>>;
+
+    // samples of one device between two unix millisecond timestamps, newest first
+    async fn query_adxl_range(
+        &self,
+        device_id: i32,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<AdxlData>> {
+        let query = AdxlQuery::new().device(device_id).between(start, end);
+        self.query_adxl(&query).await
+    }
+
+    // latest `limit` samples of one device, newest first
+    async fn query_adxl_latest(&self, device_id: i32, limit: usize) -> Result<Vec<AdxlData>> {
+        let query = AdxlQuery::new().device(device_id).limit(limit);
+        self.query_adxl(&query).await
+    }
+}
+
+// Open the backend a URL names, for daemons configured from the environment:
+// `taos://host:6030`, `sqlite://path/to/file.db`, `postgres://user@host/db`
+// or `memory:`. sqlite and postgres need their feature. The store is not
+// initialised yet.
+pub async fn open(url: &str) -> Result<Arc<dyn SensorStore>> {
+    let scheme = url.split_once(':').map_or(url, |(scheme, _)| scheme);
+    match scheme {
+        "taos" | "taosws" => Ok(Arc::new(TdengineStore::connect(url).await?)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = url.trim_start_matches("sqlite:").trim_start_matches("//");
+            Ok(Arc::new(SqliteStore::open(path)?))
+        }
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => {
+            let url = url.to_string();
+            let store = tokio::task::spawn_blocking(move || PostgresStore::connect(&url)).await??;
+            Ok(Arc::new(store))
+        }
+        "memory" => Ok(Arc::new(MemoryStore::new())),
+        _ => Err(Error::validation(format!("unsupported store url: {}", url))),
+    }
+}
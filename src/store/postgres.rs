@@ -0,0 +1,251 @@
+use async_trait::async_trait;
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use crate::errors::{Error, Result};
+use crate::models::{
+    adxl_data_v2::AdxlData, adxl_datas, humiture_data_v2::HumitureData, humiture_datas,
+};
+use crate::query::{check_no_tags, AdxlQuery, HumitureQuery, Order};
+use crate::schema::{adxl_datas as adxl_table, humiture_datas as humiture_table};
+use crate::store::SensorStore;
+use crate::{postgres::build_pool, DbPool};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+// PostgreSQL backend on the `humiture_datas` / `adxl_datas` tables.
+//
+// `humiture_datas` keeps sn and device_id as hex strings (like the v1 models)
+// and `ts` as local time, `adxl_datas.ts` is a TIMESTAMPTZ.
+pub struct PostgresStore {
+    pool: DbPool,
+}
+
+impl PostgresStore {
+    pub fn connect(database_url: &str) -> Result<Self> {
+        Ok(Self::from_pool(build_pool(database_url)?))
+    }
+
+    pub fn from_pool(pool: DbPool) -> Self {
+        PostgresStore { pool }
+    }
+
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    // diesel is blocking, run it off the async runtime
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut PgConnection) -> Result<T> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get().map_err(Error::connection)?;
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+fn local_naive(ms: i64) -> Result<NaiveDateTime> {
+    Local
+        .timestamp_millis_opt(ms)
+        .earliest()
+        .map(|dt| dt.naive_local())
+        .ok_or_else(|| Error::validation(format!("timestamp out of range: {}", ms)))
+}
+
+fn utc_naive(ms: i64) -> Result<NaiveDateTime> {
+    Utc.timestamp_millis_opt(ms)
+        .single()
+        .map(|dt| dt.naive_utc())
+        .ok_or_else(|| Error::validation(format!("timestamp out of range: {}", ms)))
+}
+
+fn to_new_humiture(data: &HumitureData) -> humiture_datas::NewHumitureData {
+    humiture_datas::NewHumitureData {
+        sn: format!("{:08x}", data.sn),
+        device_id: format!("{:016x}", data.device_id),
+        group_id: data.group_id,
+        type_id: data.type_id,
+        ts: data.ts.naive_local(),
+        temperature: data.temperature,
+        humidity: data.humidity,
+        battery: data.battery,
+        people: data.people,
+        status: data.status,
+    }
+}
+
+fn from_humiture_row(row: humiture_datas::HumitureData) -> Result<HumitureData> {
+    let ts = Local
+        .from_local_datetime(&row.ts)
+        .earliest()
+        .ok_or_else(|| Error::decode(format!("invalid local time: {}", row.ts)))?;
+    Ok(HumitureData {
+        ts,
+        sn: u32::from_str_radix(&row.sn, 16).map_err(Error::decode)? as i32,
+        device_id: u64::from_str_radix(&row.device_id, 16).map_err(Error::decode)? as i64,
+        group_id: row.group_id,
+        type_id: row.type_id,
+        temperature: row.temperature,
+        humidity: row.humidity,
+        battery: row.battery,
+        people: row.people,
+        status: row.status,
+    })
+}
+
+// the v2 samples carry no filtered / angle values, store the raw ones
+fn to_new_adxl(data: &AdxlData) -> adxl_datas::NewAdxlData {
+    adxl_datas::NewAdxlData {
+        device_id: data.device_id,
+        ts: data.ts.naive_utc(),
+        x: data.x,
+        y: data.y,
+        z: data.z,
+        xf: data.x,
+        yf: data.y,
+        zf: data.z,
+        ax: 0.0,
+        ay: 0.0,
+        az: 0.0,
+        axf: 0.0,
+        ayf: 0.0,
+        azf: 0.0,
+        t: data.t,
+        tf: data.t,
+        bat: data.bat,
+    }
+}
+
+fn from_adxl_row(row: adxl_datas::AdxlData) -> AdxlData {
+    AdxlData {
+        device_id: row.device_id,
+        ts: Utc.from_utc_datetime(&row.ts).with_timezone(&Local),
+        x: row.x,
+        y: row.y,
+        z: row.z,
+        t: row.t,
+        bat: row.bat,
+    }
+}
+
+#[async_trait]
+impl SensorStore for PostgresStore {
+    async fn init(&self) -> Result<()> {
+        self.run(|conn| {
+            conn.run_pending_migrations(MIGRATIONS)
+                .map_err(Error::Execute)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_humiture(&self, data: &HumitureData) -> Result<usize> {
+        self.insert_humiture_batch(std::slice::from_ref(data)).await
+    }
+
+    async fn insert_humiture_batch(&self, datas: &[HumitureData]) -> Result<usize> {
+        let rows: Vec<_> = datas.iter().map(to_new_humiture).collect();
+        self.run(move |conn| {
+            diesel::insert_into(humiture_table::table)
+                .values(&rows)
+                .execute(conn)
+                .map_err(Error::execute)
+        })
+        .await
+    }
+
+    async fn query_humiture(&self, query: &HumitureQuery) -> Result<Vec<HumitureData>> {
+        check_no_tags(&query.tags)?;
+        let query = query.clone();
+        let rows = self
+            .run(move |conn| {
+                let mut sql = humiture_table::table.into_boxed();
+                if let Some(device_id) = query.device_id {
+                    sql = sql.filter(humiture_table::device_id.eq(format!("{:016x}", device_id)));
+                }
+                if let Some(sn) = query.sn {
+                    sql = sql.filter(humiture_table::sn.eq(format!("{:08x}", sn)));
+                }
+                if let Some(group_id) = query.group_id {
+                    sql = sql.filter(humiture_table::group_id.eq(group_id));
+                }
+                if let Some(type_id) = query.type_id {
+                    sql = sql.filter(humiture_table::type_id.eq(type_id));
+                }
+                if let Some(start) = query.start {
+                    sql = sql.filter(humiture_table::ts.ge(local_naive(start)?));
+                }
+                if let Some(end) = query.end {
+                    sql = sql.filter(humiture_table::ts.le(local_naive(end)?));
+                }
+                sql = match query.order {
+                    Order::Asc => sql.order(humiture_table::ts.asc()),
+                    Order::Desc => sql.order(humiture_table::ts.desc()),
+                };
+                if let Some(limit) = query.limit {
+                    sql = sql.limit(limit as i64);
+                }
+                if let Some(offset) = query.offset {
+                    sql = sql.offset(offset as i64);
+                }
+                sql.load::<humiture_datas::HumitureData>(conn)
+                    .map_err(Error::execute)
+            })
+            .await?;
+        rows.into_iter().map(from_humiture_row).collect()
+    }
+
+    async fn insert_adxl(&self, data: &AdxlData) -> Result<usize> {
+        self.insert_adxl_batch(std::slice::from_ref(data)).await
+    }
+
+    async fn insert_adxl_batch(&self, datas: &[AdxlData]) -> Result<usize> {
+        let rows: Vec<_> = datas.iter().map(to_new_adxl).collect();
+        self.run(move |conn| {
+            diesel::insert_into(adxl_table::table)
+                .values(&rows)
+                .execute(conn)
+                .map_err(Error::execute)
+        })
+        .await
+    }
+
+    async fn query_adxl(&self, query: &AdxlQuery) -> Result<Vec<AdxlData>> {
+        check_no_tags(&query.tags)?;
+        let query = query.clone();
+        let rows = self
+            .run(move |conn| {
+                let mut sql = adxl_table::table.into_boxed();
+                if let Some(device_id) = query.device_id {
+                    sql = sql.filter(adxl_table::device_id.eq(device_id));
+                }
+                if let Some(start) = query.start {
+                    sql = sql.filter(adxl_table::ts.ge(utc_naive(start)?));
+                }
+                if let Some(end) = query.end {
+                    sql = sql.filter(adxl_table::ts.le(utc_naive(end)?));
+                }
+                sql = match query.order {
+                    Order::Asc => sql.order(adxl_table::ts.asc()),
+                    Order::Desc => sql.order(adxl_table::ts.desc()),
+                };
+                if let Some(limit) = query.limit {
+                    sql = sql.limit(limit as i64);
+                }
+                if let Some(offset) = query.offset {
+                    sql = sql.offset(offset as i64);
+                }
+                sql.load::<adxl_datas::AdxlData>(conn)
+                    .map_err(Error::execute)
+            })
+            .await?;
+        Ok(rows.into_iter().map(from_adxl_row).collect())
+    }
+}
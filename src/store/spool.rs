@@ -0,0 +1,536 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde_derive::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::errors::{Error, Result};
+use crate::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData, reading::Reading};
+use crate::query::{AdxlQuery, HumitureQuery};
+use crate::store::SensorStore;
+use crate::tdengine::Backoff;
+
+// Append-only file of readings that could not be written.
+//
+// The file starts with "LGPSPL" and a u16 little endian format version, then
+// each record is a u32 little endian length followed by the bincode encoded
+// `Reading`. Files of the first format have no header, they are converted
+// when opened. A record cut short by a crash is cut off when the spool is
+// opened again, so later appends start on a record boundary.
+// The file never grows past `max_bytes`, appends that do not fit fail.
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+}
+
+impl Spool {
+    pub fn open<P: AsRef<Path>>(path: P, max_bytes: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        let mut len = file.metadata()?.len();
+        if len == 0 {
+            file.write_all(&header())?;
+            file.sync_data()?;
+            len = HEADER_LEN;
+        } else {
+            let mut head = [0u8; HEADER_LEN as usize];
+            file.seek(SeekFrom::Start(0))?;
+            let tagged = len >= HEADER_LEN && {
+                file.read_exact(&mut head)?;
+                head[..MAGIC.len()] == MAGIC
+            };
+            if !tagged {
+                drop(file);
+                upgrade(&path)?;
+                return Spool::open(path, max_bytes);
+            }
+            let version = u16::from_le_bytes([head[6], head[7]]);
+            if version != VERSION {
+                return Err(Error::validation(format!(
+                    "spool {} has format {}, expected {}",
+                    path.display(),
+                    version,
+                    VERSION
+                )));
+            }
+        }
+        let size = complete_len(&mut file, len)?;
+        if size < len {
+            warn!(
+                "Cutting {} bytes of a truncated record off {}",
+                len - size,
+                path.display()
+            );
+            file.set_len(size)?;
+            file.sync_data()?;
+        }
+        Ok(Spool {
+            path,
+            file,
+            size,
+            max_bytes,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // bytes on disk, with the header
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // bytes of the records
+    pub fn pending(&self) -> u64 {
+        self.size - HEADER_LEN
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == HEADER_LEN
+    }
+
+    // all or nothing, synced before returning
+    pub fn append(&mut self, readings: &[Reading]) -> Result<()> {
+        let mut buf = Vec::new();
+        for reading in readings {
+            let record = bincode::serialize(reading).map_err(Error::decode)?;
+            buf.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&record);
+        }
+        if self.size + buf.len() as u64 > self.max_bytes {
+            return Err(Error::Io(io::Error::other(format!(
+                "spool {} is full ({} of {} bytes)",
+                self.path.display(),
+                self.size,
+                self.max_bytes
+            ))));
+        }
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    // every complete record, oldest first
+    pub fn read_all(&self) -> Result<Vec<Reading>> {
+        self.records()?
+            .map(|record| record.map(|(reading, _)| reading))
+            .collect()
+    }
+
+    // stream the records from disk, each with the offset right after it
+    pub fn records(&self) -> Result<Records> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(HEADER_LEN))?;
+        Ok(Records {
+            reader: BufReader::new(file),
+            pos: HEADER_LEN,
+            end: self.size,
+        })
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.file.set_len(HEADER_LEN)?;
+        self.file.sync_data()?;
+        self.size = HEADER_LEN;
+        Ok(())
+    }
+
+    // drop the records before `offset`, through a temp file and a rename
+    pub fn discard_front(&mut self, offset: u64) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut from = File::open(&self.path)?;
+        from.seek(SeekFrom::Start(offset))?;
+        let mut to = File::create(&tmp)?;
+        to.write_all(&header())?;
+        io::copy(&mut from.take(self.size - offset), &mut to)?;
+        to.sync_data()?;
+        fs::rename(&tmp, &self.path)?;
+        *self = Spool::open(&self.path, self.max_bytes)?;
+        Ok(())
+    }
+}
+
+// records of a spool, read through a buffer
+pub struct Records {
+    reader: BufReader<File>,
+    pos: u64,
+    end: u64,
+}
+
+impl Iterator for Records {
+    type Item = Result<(Reading, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos + 4 > self.end {
+            return None;
+        }
+        let mut prefix = [0u8; 4];
+        if let Err(e) = self.reader.read_exact(&mut prefix) {
+            return Some(Err(e.into()));
+        }
+        let len = u32::from_le_bytes(prefix) as usize;
+        let mut record = vec![0; len];
+        if let Err(e) = self.reader.read_exact(&mut record) {
+            return Some(Err(e.into()));
+        }
+        self.pos += 4 + len as u64;
+        Some(
+            bincode::deserialize(&record)
+                .map(|reading| (reading, self.pos))
+                .map_err(Error::decode),
+        )
+    }
+}
+
+// length of the header and the complete records after it
+fn complete_len(file: &mut File, len: u64) -> Result<u64> {
+    file.seek(SeekFrom::Start(HEADER_LEN))?;
+    let mut reader = BufReader::new(file);
+    let mut pos = HEADER_LEN;
+    let mut prefix = [0u8; 4];
+    while pos + 4 <= len {
+        reader.read_exact(&mut prefix)?;
+        let record = u64::from(u32::from_le_bytes(prefix));
+        if pos + 4 + record > len {
+            break;
+        }
+        reader.seek_relative(record as i64)?;
+        pos += 4 + record;
+    }
+    Ok(pos)
+}
+
+const MAGIC: [u8; 6] = *b"LGPSPL";
+
+// 2: humiture readings carry battery, people and status
+const VERSION: u16 = 2;
+
+const HEADER_LEN: u64 = 8;
+
+fn header() -> [u8; HEADER_LEN as usize] {
+    let mut header = [0u8; HEADER_LEN as usize];
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+    header[MAGIC.len()..].copy_from_slice(&VERSION.to_le_bytes());
+    header
+}
+
+// `Reading` as spooled by the first format, without a header
+#[derive(Deserialize)]
+enum ReadingV1 {
+    Humiture(HumitureDataV1),
+    Adxl(AdxlData),
+}
+
+#[derive(Deserialize)]
+struct HumitureDataV1 {
+    ts: DateTime<Local>,
+    sn: i32,
+    device_id: i64,
+    group_id: i32,
+    type_id: i32,
+    temperature: f32,
+    humidity: f32,
+}
+
+impl From<ReadingV1> for Reading {
+    fn from(reading: ReadingV1) -> Self {
+        match reading {
+            ReadingV1::Humiture(data) => Reading::Humiture(HumitureData {
+                ts: data.ts,
+                sn: data.sn,
+                device_id: data.device_id,
+                group_id: data.group_id,
+                type_id: data.type_id,
+                temperature: data.temperature,
+                humidity: data.humidity,
+                battery: 0,
+                people: false,
+                status: 0,
+            }),
+            ReadingV1::Adxl(data) => Reading::Adxl(data),
+        }
+    }
+}
+
+// rewrite a first format spool in the current one, through a temp file
+fn upgrade(path: &Path) -> Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let tmp = path.with_extension("tmp");
+    let mut to = io::BufWriter::new(File::create(&tmp)?);
+    to.write_all(&header())?;
+
+    let mut count = 0;
+    let mut prefix = [0u8; 4];
+    loop {
+        // a torn record at the end is left behind
+        match reader.read_exact(&mut prefix) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let mut record = vec![0; u32::from_le_bytes(prefix) as usize];
+        match reader.read_exact(&mut record) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let reading: ReadingV1 = bincode::deserialize(&record).map_err(Error::decode)?;
+        let record = bincode::serialize(&Reading::from(reading)).map_err(Error::decode)?;
+        to.write_all(&(record.len() as u32).to_le_bytes())?;
+        to.write_all(&record)?;
+        count += 1;
+    }
+
+    to.into_inner().map_err(|e| e.into_error())?.sync_data()?;
+    fs::rename(&tmp, path)?;
+    info!(
+        "Upgraded {} spooled readings in {} to format {}",
+        count,
+        path.display(),
+        VERSION
+    );
+    Ok(())
+}
+
+// Store wrapper that spools writes while the inner store fails.
+//
+// Failed batches are appended to the spool and reported as written. Once
+// something is spooled, later writes go behind it, so the inner store sees
+// the readings in the order they came in. A write while the spool holds
+// readings first replays it, after a failure no earlier than the backoff
+// allows, until then writes go straight to the spool without touching the
+// inner store. Replay streams the file in batches, one that fails half way
+// keeps the rest, rows can therefore be written more than once but are never
+// dropped while the spool has room. Queries go to the inner store and do not
+// see spooled rows.
+pub struct SpoolingStore<S> {
+    inner: S,
+    backoff: Backoff,
+    state: Mutex<State>,
+}
+
+struct State {
+    spool: Spool,
+    // failed replays / writes in a row
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+// readings written to the inner store at once while replaying
+const REPLAY_BATCH: usize = 500;
+
+impl<S: SensorStore> SpoolingStore<S> {
+    pub fn new(inner: S, spool: Spool) -> Self {
+        SpoolingStore {
+            inner,
+            backoff: Backoff {
+                initial: Duration::from_secs(1),
+                max: Duration::from_secs(60),
+                ..Backoff::default()
+            },
+            state: Mutex::new(State {
+                spool,
+                failures: 0,
+                retry_at: None,
+            }),
+        }
+    }
+
+    // wait between replays after a failure, `retries` is not used
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    // bytes waiting to be replayed
+    pub async fn pending(&self) -> u64 {
+        self.state.lock().await.spool.pending()
+    }
+
+    // write spooled readings to the inner store now, returns the readings
+    // written
+    pub async fn replay(&self) -> Result<usize> {
+        let mut state = self.state.lock().await;
+        let result = self.replay_locked(&mut state.spool).await;
+        self.track(&mut state, result.is_ok());
+        result
+    }
+
+    async fn replay_locked(&self, spool: &mut Spool) -> Result<usize> {
+        if spool.is_empty() {
+            return Ok(0);
+        }
+
+        let mut records = spool.records()?;
+        let mut next = records.next().transpose()?;
+        // end of the records written so far
+        let mut done_at = 0;
+        let mut done = 0;
+        while let Some((first, end)) = next.take() {
+            // runs of the same kind keep the original order
+            let mut batch = vec![first];
+            let mut batch_end = end;
+            while batch.len() < REPLAY_BATCH {
+                match records.next().transpose()? {
+                    Some((reading, end)) if same_kind(&reading, &batch[0]) => {
+                        batch.push(reading);
+                        batch_end = end;
+                    }
+                    other => {
+                        next = other;
+                        break;
+                    }
+                }
+            }
+            if next.is_none() && batch.len() == REPLAY_BATCH {
+                next = records.next().transpose()?;
+            }
+
+            if let Err(e) = self.write_run(&batch).await {
+                if done_at > 0 {
+                    spool.discard_front(done_at)?;
+                }
+                return Err(e);
+            }
+            done += batch.len();
+            done_at = batch_end;
+        }
+
+        spool.clear()?;
+        info!("Replayed {} spooled readings", done);
+        Ok(done)
+    }
+
+    // count a failure and push the next replay out, or reset after a success
+    fn track(&self, state: &mut State, ok: bool) {
+        if ok {
+            state.failures = 0;
+            state.retry_at = None;
+        } else {
+            let delay = self.backoff.delay(state.failures);
+            state.failures = state.failures.saturating_add(1);
+            state.retry_at = Some(Instant::now() + delay);
+        }
+    }
+
+    async fn write_run(&self, readings: &[Reading]) -> Result<usize> {
+        match readings.first() {
+            Some(Reading::Humiture(_)) => {
+                let datas: Vec<HumitureData> = readings
+                    .iter()
+                    .filter_map(|r| match r {
+                        Reading::Humiture(data) => Some(data.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                self.inner.insert_humiture_batch(&datas).await
+            }
+            Some(Reading::Adxl(_)) => {
+                let datas: Vec<AdxlData> = readings
+                    .iter()
+                    .filter_map(|r| match r {
+                        Reading::Adxl(data) => Some(data.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                self.inner.insert_adxl_batch(&datas).await
+            }
+            None => Ok(0),
+        }
+    }
+
+    // replay first, then write `readings` or spool them on failure
+    async fn write(&self, readings: Vec<Reading>) -> Result<usize> {
+        let mut state = self.state.lock().await;
+        let count = readings.len();
+
+        if !state.spool.is_empty() {
+            if state.retry_at.is_some_and(|at| Instant::now() < at) {
+                state.spool.append(&readings)?;
+                return Ok(count);
+            }
+            let replayed = self.replay_locked(&mut state.spool).await;
+            if let Err(e) = replayed {
+                warn!("Spool replay failed, spooling {} readings: {}", count, e);
+                self.track(&mut state, false);
+                state.spool.append(&readings)?;
+                return Ok(count);
+            }
+        }
+
+        let written = self.write_run(&readings).await;
+        self.track(&mut state, written.is_ok());
+        match written {
+            Ok(rows) => Ok(rows),
+            Err(e) => {
+                warn!("Write failed, spooling {} readings: {}", count, e);
+                state.spool.append(&readings)?;
+                Ok(count)
+            }
+        }
+    }
+}
+
+fn same_kind(a: &Reading, b: &Reading) -> bool {
+    matches!(
+        (a, b),
+        (Reading::Humiture(_), Reading::Humiture(_)) | (Reading::Adxl(_), Reading::Adxl(_))
+    )
+}
+
+#[async_trait]
+impl<S: SensorStore> SensorStore for SpoolingStore<S> {
+    async fn init(&self) -> Result<()> {
+        self.inner.init().await
+    }
+
+    async fn insert_humiture(&self, data: &HumitureData) -> Result<usize> {
+        self.insert_humiture_batch(std::slice::from_ref(data)).await
+    }
+
+    async fn insert_humiture_batch(&self, datas: &[HumitureData]) -> Result<usize> {
+        if datas.is_empty() {
+            return Ok(0);
+        }
+        self.write(datas.iter().cloned().map(Reading::Humiture).collect())
+            .await
+    }
+
+    async fn query_humiture(&self, query: &HumitureQuery) -> Result<Vec<HumitureData>> {
+        self.inner.query_humiture(query).await
+    }
+
+    async fn insert_adxl(&self, data: &AdxlData) -> Result<usize> {
+        self.insert_adxl_batch(std::slice::from_ref(data)).await
+    }
+
+    async fn insert_adxl_batch(&self, datas: &[AdxlData]) -> Result<usize> {
+        if datas.is_empty() {
+            return Ok(0);
+        }
+        self.write(datas.iter().cloned().map(Reading::Adxl).collect())
+            .await
+    }
+
+    async fn query_adxl(&self, query: &AdxlQuery) -> Result<Vec<AdxlData>> {
+        self.inner.query_adxl(query).await
+    }
+}
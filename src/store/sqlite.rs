@@ -0,0 +1,320 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local, TimeZone};
+use rusqlite::{params, params_from_iter, Connection};
+
+use crate::errors::{Error, Result};
+use crate::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+use crate::query::{check_no_tags, AdxlQuery, HumitureQuery, Order};
+use crate::store::SensorStore;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS humiture (
+    ts          INTEGER NOT NULL,
+    sn          INTEGER NOT NULL,
+    device_id   INTEGER NOT NULL,
+    group_id    INTEGER NOT NULL,
+    type_id     INTEGER NOT NULL,
+    temperature REAL    NOT NULL,
+    humidity    REAL    NOT NULL,
+    battery     INTEGER NOT NULL DEFAULT 0,
+    people      INTEGER NOT NULL DEFAULT 0,
+    status      INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS humiture_device_ts ON humiture (device_id, ts);
+CREATE INDEX IF NOT EXISTS humiture_ts ON humiture (ts);
+
+CREATE TABLE IF NOT EXISTS adxl (
+    ts        INTEGER NOT NULL,
+    device_id INTEGER NOT NULL,
+    x         REAL    NOT NULL,
+    y         REAL    NOT NULL,
+    z         REAL    NOT NULL,
+    t         REAL    NOT NULL,
+    bat       REAL    NOT NULL
+);
+CREATE INDEX IF NOT EXISTS adxl_device_ts ON adxl (device_id, ts);
+";
+
+// humiture columns added after the first release, for databases created before
+const HUMITURE_ADDED: [(&str, &str); 3] = [
+    ("battery", "INTEGER NOT NULL DEFAULT 0"),
+    ("people", "INTEGER NOT NULL DEFAULT 0"),
+    ("status", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+// Embedded SQLite backend, timestamps are stored as unix milliseconds.
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Connection::open(path).map_err(Error::connection)?;
+        // readers do not block the writer
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(Error::execute)?;
+        Ok(Self::from_connection(conn))
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory().map_err(Error::connection)?;
+        Ok(Self::from_connection(conn))
+    }
+
+    pub fn from_connection(conn: Connection) -> Self {
+        SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        }
+    }
+
+    // rusqlite is blocking, run it off the async runtime
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| Error::connection("sqlite connection poisoned"))?;
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+fn local_time(ms: i64) -> rusqlite::Result<DateTime<Local>> {
+    Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .ok_or_else(|| rusqlite::Error::IntegralValueOutOfRange(0, ms))
+}
+
+// WHERE ... ORDER BY ts ... LIMIT ... OFFSET ..., every filter value is an integer
+fn select_sql(
+    columns: &str,
+    table: &str,
+    conds: &[(&str, i64)],
+    order: Order,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> (String, Vec<i64>) {
+    let mut sql = format!("SELECT {} FROM {}", columns, table);
+    let mut values = Vec::new();
+    for (i, (cond, value)) in conds.iter().enumerate() {
+        sql.push_str(if i == 0 { " WHERE " } else { " AND " });
+        sql.push_str(cond);
+        values.push(*value);
+    }
+    sql.push_str(match order {
+        Order::Asc => " ORDER BY ts ASC",
+        Order::Desc => " ORDER BY ts DESC",
+    });
+    // sqlite only takes OFFSET after a LIMIT, -1 means no limit
+    if limit.is_some() || offset.is_some() {
+        sql.push_str(" LIMIT ?");
+        values.push(limit.map_or(-1, |limit| limit as i64));
+    }
+    if let Some(offset) = offset {
+        sql.push_str(" OFFSET ?");
+        values.push(offset as i64);
+    }
+    (sql, values)
+}
+
+#[async_trait]
+impl SensorStore for SqliteStore {
+    async fn init(&self) -> Result<()> {
+        self.run(|conn| {
+            conn.execute_batch(SCHEMA).map_err(Error::execute)?;
+
+            let mut stmt = conn
+                .prepare("SELECT name FROM pragma_table_info('humiture')")
+                .map_err(Error::prepare)?;
+            let existing = stmt
+                .query_map([], |row| row.get::<_, String>(0))
+                .map_err(Error::execute)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(Error::decode)?;
+            for (name, kind) in HUMITURE_ADDED {
+                if !existing.iter().any(|c| c == name) {
+                    conn.execute_batch(&format!(
+                        "ALTER TABLE humiture ADD COLUMN {} {}",
+                        name, kind
+                    ))
+                    .map_err(Error::execute)?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_humiture(&self, data: &HumitureData) -> Result<usize> {
+        self.insert_humiture_batch(std::slice::from_ref(data)).await
+    }
+
+    async fn insert_humiture_batch(&self, datas: &[HumitureData]) -> Result<usize> {
+        let datas = datas.to_vec();
+        self.run(move |conn| {
+            let tx = conn.transaction().map_err(Error::execute)?;
+            let mut rows = 0;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "INSERT INTO humiture (ts, sn, device_id, group_id, type_id, temperature, humidity, battery, people, status)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .map_err(Error::prepare)?;
+                for data in &datas {
+                    rows += stmt.execute(params![
+                        data.ts.timestamp_millis(),
+                        data.sn,
+                        data.device_id,
+                        data.group_id,
+                        data.type_id,
+                        data.temperature,
+                        data.humidity,
+                        data.battery,
+                        data.people,
+                        data.status,
+                    ])
+                        .map_err(Error::execute)?;
+                }
+            }
+            tx.commit().map_err(Error::execute)?;
+            Ok(rows)
+        })
+        .await
+    }
+
+    async fn query_humiture(&self, query: &HumitureQuery) -> Result<Vec<HumitureData>> {
+        check_no_tags(&query.tags)?;
+        let mut conds = Vec::new();
+        if let Some(device_id) = query.device_id {
+            conds.push(("device_id = ?", device_id));
+        }
+        if let Some(sn) = query.sn {
+            conds.push(("sn = ?", sn as i64));
+        }
+        if let Some(group_id) = query.group_id {
+            conds.push(("group_id = ?", group_id as i64));
+        }
+        if let Some(type_id) = query.type_id {
+            conds.push(("type_id = ?", type_id as i64));
+        }
+        if let Some(start) = query.start {
+            conds.push(("ts >= ?", start));
+        }
+        if let Some(end) = query.end {
+            conds.push(("ts <= ?", end));
+        }
+        let (sql, values) = select_sql(
+            "ts, sn, device_id, group_id, type_id, temperature, humidity, battery, people, status",
+            "humiture",
+            &conds,
+            query.order,
+            query.limit,
+            query.offset,
+        );
+
+        self.run(move |conn| {
+            let mut stmt = conn.prepare_cached(&sql).map_err(Error::prepare)?;
+            let rows = stmt
+                .query_map(params_from_iter(values), |row| {
+                    Ok(HumitureData {
+                        ts: local_time(row.get(0)?)?,
+                        sn: row.get(1)?,
+                        device_id: row.get(2)?,
+                        group_id: row.get(3)?,
+                        type_id: row.get(4)?,
+                        temperature: row.get(5)?,
+                        humidity: row.get(6)?,
+                        battery: row.get(7)?,
+                        people: row.get(8)?,
+                        status: row.get(9)?,
+                    })
+                })
+                .map_err(Error::execute)?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(Error::decode)
+        })
+        .await
+    }
+
+    async fn insert_adxl(&self, data: &AdxlData) -> Result<usize> {
+        self.insert_adxl_batch(std::slice::from_ref(data)).await
+    }
+
+    async fn insert_adxl_batch(&self, datas: &[AdxlData]) -> Result<usize> {
+        let datas = datas.to_vec();
+        self.run(move |conn| {
+            let tx = conn.transaction().map_err(Error::execute)?;
+            let mut rows = 0;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "INSERT INTO adxl (ts, device_id, x, y, z, t, bat) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .map_err(Error::prepare)?;
+                for data in &datas {
+                    rows += stmt.execute(params![
+                        data.ts.timestamp_millis(),
+                        data.device_id,
+                        data.x,
+                        data.y,
+                        data.z,
+                        data.t,
+                        data.bat,
+                    ])
+                        .map_err(Error::execute)?;
+                }
+            }
+            tx.commit().map_err(Error::execute)?;
+            Ok(rows)
+        })
+        .await
+    }
+
+    async fn query_adxl(&self, query: &AdxlQuery) -> Result<Vec<AdxlData>> {
+        check_no_tags(&query.tags)?;
+        let mut conds = Vec::new();
+        if let Some(device_id) = query.device_id {
+            conds.push(("device_id = ?", device_id as i64));
+        }
+        if let Some(start) = query.start {
+            conds.push(("ts >= ?", start));
+        }
+        if let Some(end) = query.end {
+            conds.push(("ts <= ?", end));
+        }
+        let (sql, values) = select_sql(
+            "ts, device_id, x, y, z, t, bat",
+            "adxl",
+            &conds,
+            query.order,
+            query.limit,
+            query.offset,
+        );
+
+        self.run(move |conn| {
+            let mut stmt = conn.prepare_cached(&sql).map_err(Error::prepare)?;
+            let rows = stmt
+                .query_map(params_from_iter(values), |row| {
+                    Ok(AdxlData {
+                        ts: local_time(row.get(0)?)?,
+                        device_id: row.get(1)?,
+                        x: row.get(2)?,
+                        y: row.get(3)?,
+                        z: row.get(4)?,
+                        t: row.get(5)?,
+                        bat: row.get(6)?,
+                    })
+                })
+                .map_err(Error::execute)?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(Error::decode)
+        })
+        .await
+    }
+}
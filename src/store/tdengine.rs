@@ -0,0 +1,523 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use taos::*;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::errors::{Error, Result};
+use crate::models::adxl_data_v2::AdxlData;
+use crate::models::humiture_data_v2::HumitureData;
+use crate::query::{check_ident, AdxlQuery, HumitureQuery};
+use crate::store::SensorStore;
+use crate::tdengine::{build_taos_pool, get_conn, Backoff, TaosConn, TaosPool};
+
+// database and super table a kind of reading lives in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TdTable {
+    database: String,
+    stable: String,
+}
+
+impl TdTable {
+    // names end up in SQL text, so only plain identifiers are accepted
+    pub fn new(database: &str, stable: &str) -> Result<Self> {
+        Ok(TdTable {
+            database: check_ident(database)?.to_string(),
+            stable: check_ident(stable)?.to_string(),
+        })
+    }
+
+    pub fn database(&self) -> &str {
+        &self.database
+    }
+
+    pub fn stable(&self) -> &str {
+        &self.stable
+    }
+
+    // <db>.<stable>
+    pub fn qualified(&self) -> String {
+        format!("{}.{}", self.database, self.stable)
+    }
+
+    // <db>.g000001, one sub table per group / device
+    pub fn sub_table(&self, id: i64) -> String {
+        format!("{}.g{:06}", self.database, id)
+    }
+}
+
+// TDengine backend.
+//
+// The handle remembers which database / super table humiture and ADXL data
+// go to, defaults are `humiture.humiture` and `adxl355.adxl355`. Connections
+// come from an r2d2 pool that checks them on checkout, a broken connection
+// is replaced and an unreachable server is retried with backoff.
+pub struct TdengineStore {
+    pool: TaosPool,
+    backoff: Backoff,
+    humiture: TdTable,
+    adxl: TdTable,
+    // prepared on the first insert, reused afterwards
+    humiture_writer: OnceCell<HumitureWriter>,
+    adxl_writer: OnceCell<AdxlWriter>,
+}
+
+impl TdengineStore {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let database_url = database_url.to_string();
+        let pool = tokio::task::spawn_blocking(move || build_taos_pool(&database_url)).await??;
+        Ok(Self::from_pool(pool))
+    }
+
+    pub fn from_pool(pool: TaosPool) -> Self {
+        TdengineStore {
+            pool,
+            backoff: Backoff::default(),
+            humiture: TdTable {
+                database: "humiture".to_string(),
+                stable: "humiture".to_string(),
+            },
+            adxl: TdTable {
+                database: "adxl355".to_string(),
+                stable: "adxl355".to_string(),
+            },
+            humiture_writer: OnceCell::new(),
+            adxl_writer: OnceCell::new(),
+        }
+    }
+
+    pub fn with_humiture_table(mut self, database: &str, stable: &str) -> Result<Self> {
+        self.humiture = TdTable::new(database, stable)?;
+        self.humiture_writer = OnceCell::new();
+        Ok(self)
+    }
+
+    pub fn with_adxl_table(mut self, database: &str, stable: &str) -> Result<Self> {
+        self.adxl = TdTable::new(database, stable)?;
+        self.adxl_writer = OnceCell::new();
+        Ok(self)
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn pool(&self) -> &TaosPool {
+        &self.pool
+    }
+
+    // a checked connection from the pool
+    pub async fn conn(&self) -> Result<TaosConn> {
+        get_conn(&self.pool, &self.backoff).await
+    }
+
+    pub async fn health_check(&self) -> Result<()> {
+        let conn = self.conn().await?;
+        conn.exec("SELECT SERVER_STATUS()")
+            .await
+            .map_err(Error::connection)?;
+        Ok(())
+    }
+
+    pub fn humiture_table(&self) -> &TdTable {
+        &self.humiture
+    }
+
+    pub fn adxl_table(&self) -> &TdTable {
+        &self.adxl
+    }
+
+    // a writer with its own prepared statement, for ingestion tasks that
+    // should not queue behind the store's shared one
+    pub async fn humiture_writer(&self) -> Result<HumitureWriter> {
+        HumitureWriter::new(self.pool.clone(), self.backoff, self.humiture.clone()).await
+    }
+
+    pub async fn adxl_writer(&self) -> Result<AdxlWriter> {
+        AdxlWriter::new(self.pool.clone(), self.backoff, self.adxl.clone()).await
+    }
+
+    pub async fn init_humiture(&self) -> Result<()> {
+        let taos = self.conn().await?;
+        taos.create_database(&self.humiture.database)
+            .await
+            .map_err(Error::execute)?;
+        taos.exec(humiture_stable_sql(&self.humiture.qualified()))
+            .await
+            .map_err(Error::execute)?;
+
+        // super tables created before battery / people / status existed
+        let existing: Vec<Field> = self
+            .fetch(&format!("DESCRIBE {}", self.humiture.qualified()))
+            .await?;
+        for (name, kind) in &HUMITURE_COLUMNS[HUMITURE_V1_COLUMNS..] {
+            if !existing.iter().any(|f| f.field == *name) {
+                let sql = format!(
+                    "ALTER STABLE {} ADD COLUMN {} {}",
+                    self.humiture.qualified(),
+                    name,
+                    kind
+                );
+                taos.exec(&sql).await.map_err(Error::execute)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn init_adxl(&self) -> Result<()> {
+        let taos = self.conn().await?;
+        taos.create_database(&self.adxl.database)
+            .await
+            .map_err(Error::execute)?;
+        taos.exec(adxl_stable_sql(&self.adxl.qualified()))
+            .await
+            .map_err(Error::execute)?;
+        Ok(())
+    }
+
+    async fn fetch<T: DeserializeOwned + Send + 'static>(&self, sql: &str) -> Result<Vec<T>> {
+        debug!("{}", sql);
+        let taos = self.conn().await?;
+        let mut result = taos.query(sql).await.map_err(Error::execute)?;
+        let records = result
+            .deserialize()
+            .try_collect()
+            .await
+            .map_err(Error::decode)?;
+        Ok(records)
+    }
+}
+
+// One INSERT statement on a super table, prepared once and reused. Each
+// write groups the rows by sub table, switches to it with set_tbname_tags and
+// binds whole columns, then executes the lot in one round trip.
+//
+// Calls are serialized on the statement. If one fails the statement and its
+// connection are dropped, the next write prepares again on a connection
+// fresh from the pool.
+struct StmtWriter {
+    pool: TaosPool,
+    backoff: Backoff,
+    table: TdTable,
+    // number of columns in the super table
+    width: usize,
+    prepared: Mutex<Option<Prepared>>,
+}
+
+// the statement is declared first so it is dropped before its connection
+struct Prepared {
+    stmt: Stmt,
+    _conn: TaosConn,
+}
+
+impl StmtWriter {
+    async fn new(pool: TaosPool, backoff: Backoff, table: TdTable, width: usize) -> Result<Self> {
+        let prepared = prepare_insert(&pool, &backoff, &table, width).await?;
+        Ok(StmtWriter {
+            pool,
+            backoff,
+            table,
+            width,
+            prepared: Mutex::new(Some(prepared)),
+        })
+    }
+
+    async fn write<T>(
+        &self,
+        datas: &[T],
+        sub_table: impl Fn(&T) -> i32,
+        columns: impl Fn(&[&T]) -> Vec<ColumnView>,
+    ) -> Result<usize> {
+        if datas.is_empty() {
+            return Ok(0);
+        }
+
+        let mut groups: BTreeMap<i32, Vec<&T>> = BTreeMap::new();
+        for data in datas {
+            groups.entry(sub_table(data)).or_default().push(data);
+        }
+
+        let mut guard = self.prepared.lock().await;
+        let mut prepared = match guard.take() {
+            Some(prepared) => prepared,
+            None => prepare_insert(&self.pool, &self.backoff, &self.table, self.width).await?,
+        };
+
+        let rows = bind_and_execute(&mut prepared.stmt, &self.table, &groups, columns).await?;
+        debug!(
+            "Inserted {} rows into {} sub tables of {}",
+            rows,
+            groups.len(),
+            self.table.qualified()
+        );
+
+        // only a statement that went through cleanly is kept
+        *guard = Some(prepared);
+        Ok(rows)
+    }
+}
+
+async fn prepare_insert(
+    pool: &TaosPool,
+    backoff: &Backoff,
+    table: &TdTable,
+    width: usize,
+) -> Result<Prepared> {
+    let conn = get_conn(pool, backoff).await?;
+    let taos: &Taos = &conn;
+    let mut stmt = Stmt::init(taos).await.map_err(Error::prepare)?;
+    let sql = format!(
+        "INSERT INTO ? USING {} TAGS(?) VALUES({})",
+        table.qualified(),
+        vec!["?"; width].join(", ")
+    );
+    stmt.prepare(&sql).await.map_err(Error::prepare)?;
+    Ok(Prepared { stmt, _conn: conn })
+}
+
+async fn bind_and_execute<T>(
+    stmt: &mut Stmt,
+    table: &TdTable,
+    groups: &BTreeMap<i32, Vec<&T>>,
+    columns: impl Fn(&[&T]) -> Vec<ColumnView>,
+) -> Result<usize> {
+    for (id, rows) in groups {
+        // bind table name and tags
+        stmt.set_tbname_tags(&table.sub_table(i64::from(*id)), &[taos::Value::Int(*id)])
+            .await
+            .map_err(Error::bind)?;
+
+        // bind values.
+        stmt.bind(&columns(rows)).await.map_err(Error::bind)?;
+        stmt.add_batch().await.map_err(Error::bind)?;
+    }
+
+    stmt.execute().await.map_err(Error::execute)
+}
+
+fn humiture_columns(rows: &[&HumitureData]) -> Vec<ColumnView> {
+    vec![
+        ColumnView::from_millis_timestamp(rows.iter().map(|r| r.ts.timestamp_millis()).collect()),
+        ColumnView::from_ints(rows.iter().map(|r| r.sn).collect()),
+        ColumnView::from_big_ints(rows.iter().map(|r| r.device_id).collect()),
+        ColumnView::from_ints(rows.iter().map(|r| r.group_id).collect()),
+        ColumnView::from_ints(rows.iter().map(|r| r.type_id).collect()),
+        ColumnView::from_floats(rows.iter().map(|r| r.temperature).collect()),
+        ColumnView::from_floats(rows.iter().map(|r| r.humidity).collect()),
+        ColumnView::from_ints(rows.iter().map(|r| r.battery).collect()),
+        ColumnView::from_bools(rows.iter().map(|r| r.people).collect()),
+        ColumnView::from_ints(rows.iter().map(|r| r.status).collect()),
+    ]
+}
+
+fn adxl_columns(rows: &[&AdxlData]) -> Vec<ColumnView> {
+    vec![
+        ColumnView::from_millis_timestamp(rows.iter().map(|r| r.ts.timestamp_millis()).collect()),
+        ColumnView::from_ints(rows.iter().map(|r| r.device_id).collect()),
+        ColumnView::from_floats(rows.iter().map(|r| r.x).collect()),
+        ColumnView::from_floats(rows.iter().map(|r| r.y).collect()),
+        ColumnView::from_floats(rows.iter().map(|r| r.z).collect()),
+        ColumnView::from_floats(rows.iter().map(|r| r.t).collect()),
+        ColumnView::from_floats(rows.iter().map(|r| r.bat).collect()),
+    ]
+}
+
+// Long lived writer for humiture readings, one sub table per group.
+// Share it between ingestion tasks with an Arc.
+pub struct HumitureWriter {
+    inner: StmtWriter,
+}
+
+impl HumitureWriter {
+    pub async fn new(pool: TaosPool, backoff: Backoff, table: TdTable) -> Result<Self> {
+        Ok(HumitureWriter {
+            inner: StmtWriter::new(pool, backoff, table, HUMITURE_COLUMNS.len()).await?,
+        })
+    }
+
+    pub fn table(&self) -> &TdTable {
+        &self.inner.table
+    }
+
+    pub async fn write(&self, data: &HumitureData) -> Result<usize> {
+        self.write_batch(std::slice::from_ref(data)).await
+    }
+
+    pub async fn write_batch(&self, datas: &[HumitureData]) -> Result<usize> {
+        self.inner
+            .write(datas, |data| data.group_id, humiture_columns)
+            .await
+    }
+}
+
+// Long lived writer for ADXL samples, one g{device_id} sub table per device.
+// Share it between ingestion tasks with an Arc.
+pub struct AdxlWriter {
+    inner: StmtWriter,
+}
+
+impl AdxlWriter {
+    pub async fn new(pool: TaosPool, backoff: Backoff, table: TdTable) -> Result<Self> {
+        Ok(AdxlWriter {
+            inner: StmtWriter::new(pool, backoff, table, ADXL_WIDTH).await?,
+        })
+    }
+
+    pub fn table(&self) -> &TdTable {
+        &self.inner.table
+    }
+
+    pub async fn write(&self, data: &AdxlData) -> Result<usize> {
+        self.write_batch(std::slice::from_ref(data)).await
+    }
+
+    pub async fn write_batch(&self, datas: &[AdxlData]) -> Result<usize> {
+        self.inner
+            .write(datas, |data| data.device_id, adxl_columns)
+            .await
+    }
+}
+
+// super table of humiture readings, one sub table per group
+fn humiture_stable_sql(name: &str) -> String {
+    format!(
+        "CREATE STABLE if NOT EXISTS {} (
+    ts          TIMESTAMP,
+    sn          INT      ,
+    device_id   BIGINT   ,
+    group_id    INT      ,
+    type_id     INT      ,
+    temperature FLOAT    ,
+    humidity    FLOAT    ,
+    battery     INT      ,
+    people      BOOL     ,
+    status      INT      )
+    TAGS     (groupId INT)
+    ",
+        name
+    )
+}
+
+// a row of DESCRIBE, the other columns are left out
+#[derive(Deserialize)]
+struct Field {
+    field: String,
+}
+
+// a humiture row as read back, rows written before battery / people / status
+// were added hold NULL there
+#[derive(Deserialize)]
+struct HumitureRow {
+    ts: DateTime<Local>,
+    sn: i32,
+    device_id: i64,
+    group_id: i32,
+    type_id: i32,
+    temperature: f32,
+    humidity: f32,
+    battery: Option<i32>,
+    people: Option<bool>,
+    status: Option<i32>,
+}
+
+impl From<HumitureRow> for HumitureData {
+    fn from(row: HumitureRow) -> Self {
+        HumitureData {
+            ts: row.ts,
+            sn: row.sn,
+            device_id: row.device_id,
+            group_id: row.group_id,
+            type_id: row.type_id,
+            temperature: row.temperature,
+            humidity: row.humidity,
+            battery: row.battery.unwrap_or_default(),
+            people: row.people.unwrap_or_default(),
+            status: row.status.unwrap_or_default(),
+        }
+    }
+}
+
+// every humiture column, the ones added after the first release last
+const HUMITURE_COLUMNS: [(&str, &str); 10] = [
+    ("ts", "TIMESTAMP"),
+    ("sn", "INT"),
+    ("device_id", "BIGINT"),
+    ("group_id", "INT"),
+    ("type_id", "INT"),
+    ("temperature", "FLOAT"),
+    ("humidity", "FLOAT"),
+    ("battery", "INT"),
+    ("people", "BOOL"),
+    ("status", "INT"),
+];
+
+// columns of the first release, older super tables stop here
+const HUMITURE_V1_COLUMNS: usize = 7;
+
+const ADXL_WIDTH: usize = 7;
+
+// super table of ADXL samples, one sub table per device
+fn adxl_stable_sql(name: &str) -> String {
+    format!(
+        "CREATE STABLE if NOT EXISTS {} (
+    ts        TIMESTAMP ,
+    device_id INT       ,
+    x         FLOAT     ,
+    y         FLOAT     ,
+    z         FLOAT     ,
+    t         FLOAT     ,
+    bat       FLOAT     )
+    TAGS     (groupId INT)
+    ",
+        name
+    )
+}
+
+#[async_trait]
+impl SensorStore for TdengineStore {
+    async fn init(&self) -> Result<()> {
+        self.init_humiture().await?;
+        self.init_adxl().await
+    }
+
+    async fn insert_humiture(&self, data: &HumitureData) -> Result<usize> {
+        self.insert_humiture_batch(std::slice::from_ref(data)).await
+    }
+
+    // one statement execution for the whole slice, see StmtWriter
+    async fn insert_humiture_batch(&self, datas: &[HumitureData]) -> Result<usize> {
+        self.humiture_writer
+            .get_or_try_init(|| self.humiture_writer())
+            .await?
+            .write_batch(datas)
+            .await
+    }
+
+    async fn query_humiture(&self, query: &HumitureQuery) -> Result<Vec<HumitureData>> {
+        let sql = query.to_select(&self.humiture.qualified())?.to_sql();
+        let rows: Vec<HumitureRow> = self.fetch(&sql).await?;
+        Ok(rows.into_iter().map(HumitureData::from).collect())
+    }
+
+    async fn insert_adxl(&self, data: &AdxlData) -> Result<usize> {
+        self.insert_adxl_batch(std::slice::from_ref(data)).await
+    }
+
+    async fn insert_adxl_batch(&self, datas: &[AdxlData]) -> Result<usize> {
+        self.adxl_writer
+            .get_or_try_init(|| self.adxl_writer())
+            .await?
+            .write_batch(datas)
+            .await
+    }
+
+    async fn query_adxl(&self, query: &AdxlQuery) -> Result<Vec<AdxlData>> {
+        let sql = query.to_select(&self.adxl.qualified())?.to_sql();
+        self.fetch(&sql).await
+    }
+}
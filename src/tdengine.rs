@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use log::warn;
+use taos::sync::{Queryable, TBuilder};
+use taos::{Taos, TaosBuilder};
+
+use crate::errors::{Error, Result};
+
+pub type TaosPool = r2d2::Pool<TaosConnectionManager>;
+pub type TaosConn = r2d2::PooledConnection<TaosConnectionManager>;
+
+// r2d2 manager for TDengine connections, built from a `taos://` DSN
+pub struct TaosConnectionManager {
+    builder: TaosBuilder,
+}
+
+impl TaosConnectionManager {
+    pub fn new(database_url: &str) -> Result<Self> {
+        let builder =
+            <TaosBuilder as TBuilder>::from_dsn(database_url).map_err(Error::connection)?;
+        Ok(TaosConnectionManager { builder })
+    }
+}
+
+impl r2d2::ManageConnection for TaosConnectionManager {
+    type Connection = Taos;
+    type Error = taos::Error;
+
+    fn connect(&self) -> std::result::Result<Taos, taos::Error> {
+        <TaosBuilder as TBuilder>::build(&self.builder)
+    }
+
+    // health check run when a connection is checked out of the pool
+    fn is_valid(&self, conn: &mut Taos) -> std::result::Result<(), taos::Error> {
+        Queryable::exec(conn, "SELECT SERVER_STATUS()").map(|_| ())
+    }
+
+    fn has_broken(&self, _conn: &mut Taos) -> bool {
+        false
+    }
+}
+
+pub fn build_taos_pool(database_url: &str) -> Result<TaosPool> {
+    let manager = TaosConnectionManager::new(database_url)?;
+    Ok(r2d2::Pool::builder()
+        .max_size(8)
+        .min_idle(Some(1))
+        .test_on_check_out(true)
+        .connection_timeout(Duration::from_secs(10))
+        // don't fail at startup while the server is down, `get_conn` waits
+        .build_unchecked(manager))
+}
+
+// how long `get_conn` waits on the pool before backing off
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// exponential backoff between reconnect attempts
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    // attempts after the first one
+    pub retries: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(200),
+            max: Duration::from_secs(10),
+            retries: 5,
+        }
+    }
+}
+
+impl Backoff {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max)
+    }
+}
+
+// check a connection out of the pool, waiting with backoff while the server
+// is unreachable
+pub async fn get_conn(pool: &TaosPool, backoff: &Backoff) -> Result<TaosConn> {
+    let mut attempt = 0;
+    loop {
+        let pool = pool.clone();
+        match tokio::task::spawn_blocking(move || pool.get_timeout(CHECKOUT_TIMEOUT)).await? {
+            Ok(conn) => return Ok(conn),
+            Err(e) if attempt < backoff.retries => {
+                let delay = backoff.delay(attempt);
+                warn!("TDengine unavailable ({}), retrying in {:?}", e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(Error::connection(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use super::Backoff;
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            retries: 10,
+        };
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(400));
+        assert_eq!(backoff.delay(4), Duration::from_secs(1));
+        assert_eq!(backoff.delay(40), Duration::from_secs(1));
+    }
+}
@@ -3,9 +3,11 @@ mod test_adxl {
 
     use chrono::{Duration, Local};
     use lgp_iot_db::models::adxl_data_v2::{
-        init_tdengine_adxl, insert_adxl, query_adxl_by_date, query_adxl_by_group, query_adxl_by_id,
-        AdxlData,
+        init_tdengine_adxl, insert_adxl, insert_adxl_batch, query_adxl_by_date,
+        query_adxl_by_group, query_adxl_by_id, AdxlData,
     };
+    use lgp_iot_db::query::AdxlQuery;
+    use lgp_iot_db::store::{MemoryStore, SensorStore};
     use std::{env, sync::Once};
     use tokio::test;
 
@@ -19,6 +21,7 @@ mod test_adxl {
     }
 
     #[test]
+    #[ignore = "requires a local TDengine server"]
     async fn test_insert() {
         init();
         let taos = init_tdengine_adxl("taos://localhost:6030", "adxl")
@@ -32,6 +35,70 @@ mod test_adxl {
     }
 
     #[test]
+    #[ignore = "requires a local TDengine server"]
+    async fn test_insert_batch() {
+        init();
+        let taos = init_tdengine_adxl("taos://localhost:6030", "adxl")
+            .await
+            .unwrap();
+
+        // 100 samples spread over two devices
+        let now = Local::now();
+        let datas: Vec<AdxlData> = (0..100)
+            .map(|i| {
+                let mut data = AdxlData::_random();
+                data.device_id = 1 + i % 2;
+                data.ts = now - Duration::milliseconds(10 * i as i64);
+                data
+            })
+            .collect();
+        let result = insert_adxl_batch(&datas, &taos).await.unwrap();
+
+        assert_eq!(result, 100);
+    }
+
+    #[test]
+    async fn test_memory_query() {
+        init();
+
+        let store = MemoryStore::new();
+        store.init().await.unwrap();
+
+        // one sample every 30 seconds over the last 30 minutes
+        let now = Local::now();
+        for i in 0..60 {
+            let mut data = AdxlData::test_wave(1.0, i as f32 * 6.0);
+            data.ts = now - Duration::seconds(30 * i);
+            store.insert_adxl(&data).await.unwrap();
+        }
+        let mut other = AdxlData::_random();
+        other.device_id = 1;
+        store.insert_adxl_batch(&[other]).await.unwrap();
+
+        // query by date
+        let last = now - Duration::minutes(30);
+        let records = store
+            .query_adxl_range(9999, last.timestamp_millis(), now.timestamp_millis())
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 60);
+        assert!(records.windows(2).all(|w| w[0].ts >= w[1].ts));
+
+        // query by id
+        let records = store.query_adxl_latest(9999, 10).await.unwrap();
+        assert_eq!(records.len(), 10);
+        assert_eq!(records[0].ts, now);
+
+        // no filter, limit only
+        let records = store
+            .query_adxl(&AdxlQuery::new().limit(100))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 61);
+    }
+
+    #[test]
+    #[ignore = "requires the TDengine server at db.21up.cn"]
     async fn test_query() {
         init();
         let taos = init_tdengine_adxl("taos://db.21up.cn:6030", "adxl")
@@ -42,15 +109,17 @@ mod test_adxl {
         let now = Local::now();
         let last = now - Duration::minutes(30);
         let records =
-            query_adxl_by_date(&taos, 9999, last.timestamp_millis(), now.timestamp_millis()).await;
+            query_adxl_by_date(&taos, 9999, last.timestamp_millis(), now.timestamp_millis())
+                .await
+                .unwrap();
         assert_eq!(records.len(), 60);
 
         // query by id
-        let records = query_adxl_by_id(&taos, 9999, 10).await;
+        let records = query_adxl_by_id(&taos, 9999, 10).await.unwrap();
         assert_eq!(records.len(), 10);
 
         // query by group
-        let records = query_adxl_by_group(&taos, 9999, 10).await;
+        let records = query_adxl_by_group(&taos, 9999, 10).await.unwrap();
         assert_eq!(records.len(), 10);
     }
 }
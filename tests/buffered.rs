@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod test_buffered {
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::test;
+
+    use lgp_iot_db::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+    use lgp_iot_db::query::{AdxlQuery, HumitureQuery};
+    use lgp_iot_db::store::{BufferConfig, BufferedWriter, MemoryStore, SensorStore};
+
+    async fn count(store: &MemoryStore) -> (usize, usize) {
+        let humitures = store.query_humiture(&HumitureQuery::new()).await.unwrap();
+        let adxls = store.query_adxl(&AdxlQuery::new()).await.unwrap();
+        (humitures.len(), adxls.len())
+    }
+
+    #[test]
+    async fn test_flush_on_rows() {
+        let store = Arc::new(MemoryStore::new());
+        let writer = BufferedWriter::spawn(
+            store.clone(),
+            BufferConfig {
+                max_rows: 10,
+                max_delay: Duration::from_secs(60),
+                capacity: 100,
+            },
+        );
+
+        for i in 0..25 {
+            let data = HumitureData::new(i, 0x0000111122223333, 0, 0, 20.0, 50.0);
+            writer.write(data).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // two full batches, the rest waits for the timer
+        assert_eq!(count(&store).await, (20, 0));
+
+        let stats = writer.shutdown().await.unwrap();
+        assert_eq!(count(&store).await, (25, 0));
+        assert_eq!(stats.flushes, 3);
+        assert_eq!(stats.rows, 25);
+        assert_eq!(stats.failed, 0);
+    }
+
+    #[test]
+    async fn test_flush_on_time() {
+        let store = Arc::new(MemoryStore::new());
+        let writer = BufferedWriter::spawn(
+            store.clone(),
+            BufferConfig {
+                max_rows: 1000,
+                max_delay: Duration::from_millis(50),
+                capacity: 100,
+            },
+        );
+
+        writer
+            .write(HumitureData::new(1, 0x0000111122223333, 0, 0, 20.0, 50.0))
+            .await
+            .unwrap();
+        writer.write(AdxlData::_random()).await.unwrap();
+        assert_eq!(count(&store).await, (0, 0));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(count(&store).await, (1, 1));
+
+        let stats = writer.shutdown().await.unwrap();
+        assert_eq!(stats.flushes, 1);
+        assert_eq!(stats.rows, 2);
+    }
+
+    #[test]
+    async fn test_shutdown() {
+        let store = Arc::new(MemoryStore::new());
+        let writer = BufferedWriter::spawn(store.clone(), BufferConfig::default());
+
+        // producers keep their own handles
+        let sender = writer.sender();
+        let producer = tokio::spawn(async move {
+            for i in 0..100 {
+                sender
+                    .write(AdxlData::test_wave(1.0, i as f32))
+                    .await
+                    .unwrap();
+            }
+            sender
+        });
+        let sender = producer.await.unwrap();
+
+        // everything queued before the shutdown is written
+        let stats = writer.shutdown().await.unwrap();
+        assert_eq!(stats.rows, 100);
+        assert_eq!(count(&store).await, (0, 100));
+
+        // and later writes are refused
+        assert!(sender.write(AdxlData::_random()).await.is_err());
+    }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+#[cfg(test)]
+mod test_codec {
+
+    use futures::{SinkExt, StreamExt};
+    use lgp_iot_db::models::humiture_data_v2::HumitureData;
+    use lgp_iot_db::protocol::{CodecStats, HumitureCodec};
+    use tokio::io::AsyncWriteExt;
+    use tokio::test;
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    use crate::common::twelve_readings;
+
+    #[test]
+    async fn test_read() {
+        let single = HumitureData::new(1, 0x0000111122223333, 1, 2, 21.5, 40.0)
+            .to_bytes()
+            .unwrap();
+        let multi = HumitureData::encode(&twelve_readings(), 0).unwrap();
+        let mut corrupt = single.clone();
+        corrupt[20] ^= 0xFF;
+
+        let mut stream = vec![0x00, 0x13];
+        stream.extend_from_slice(&single);
+        stream.extend_from_slice(&corrupt);
+        stream.extend_from_slice(&multi);
+        stream.extend_from_slice(&single[..10]);
+
+        let (mut tx, rx) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            for chunk in stream.chunks(7) {
+                tx.write_all(chunk).await.unwrap();
+            }
+        });
+
+        let mut framed = FramedRead::new(rx, HumitureCodec::new());
+        let mut batches = Vec::new();
+        while let Some(frame) = framed.next().await {
+            batches.push(frame.unwrap().samples);
+        }
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 1);
+        assert_eq!(batches[0][0].temperature, 21.5);
+        assert_eq!(batches[1].len(), 12);
+        assert_eq!(
+            framed.decoder().stats(),
+            CodecStats {
+                frames: 2,
+                failed: 1,
+                discarded: (2 + corrupt.len() + 10) as u64,
+            }
+        );
+    }
+
+    #[test]
+    async fn test_round_trip() {
+        let (tx, rx) = tokio::io::duplex(1024);
+        let mut writer = FramedWrite::new(tx, HumitureCodec::new());
+        let mut reader = FramedRead::new(rx, HumitureCodec::new());
+
+        let datas = twelve_readings();
+        writer.send(&datas[..]).await.unwrap();
+        writer.send(&datas[..1]).await.unwrap();
+        // not a frame layout
+        assert!(writer.send(&datas[..2]).await.is_err());
+
+        let frame = reader.next().await.unwrap().unwrap();
+        assert_eq!(frame.samples.len(), 12);
+        assert_eq!(frame.samples[11].ts, datas[11].ts);
+        assert_eq!(frame.samples[11].temperature, datas[11].temperature);
+        let frame = reader.next().await.unwrap().unwrap();
+        assert_eq!(frame.samples.len(), 1);
+    }
+}
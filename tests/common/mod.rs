@@ -0,0 +1,40 @@
+// Fixtures shared by the integration tests, each test crate uses a part.
+#![allow(dead_code)]
+
+use chrono::{Duration, Local, Timelike};
+use lgp_iot_db::models::humiture_data_v2::HumitureData;
+use lgp_iot_db::protocol::crc8;
+use lgp_iot_db::protocol::humiture::payload_len;
+
+// 24 samples of device 0xAEE6070000001F3B, sent 2021-11-02 10:02:04
+pub const FRAME_24: &str = "5aa576aee6070000001f3b470000000002150b020a020400c200c200c200c200c300c300c300c400c500c600c600c700c700be00bf00bf00c000c100c100c200c300c400c600c602ab02af02b102b202b402b102b402b402af02ae02ac02a502a0029c0296028f028c0282027a026e026d0266025f025d62007c";
+
+// raw frame of 12 samples at 20.0℃ / 50.0%, 10 minute interval
+pub fn twelve_frame() -> Vec<u8> {
+    let mut bytes = vec![0x5A, 0xA5, payload_len(12) as u8];
+    bytes.extend_from_slice(&0x0000111122223333i64.to_be_bytes());
+    bytes.extend_from_slice(&1i32.to_be_bytes());
+    bytes.extend_from_slice(&[1, 2, 24, 5, 6, 12, 0, 0]);
+    for _ in 0..12 {
+        bytes.extend_from_slice(&200i16.to_be_bytes());
+    }
+    for _ in 0..12 {
+        bytes.extend_from_slice(&500i16.to_be_bytes());
+    }
+    bytes.extend_from_slice(&[0x63, 0x01 << 1]);
+    let crc = crc8(&bytes[3..]);
+    bytes.push(crc);
+    bytes
+}
+
+// 12 readings 5 minutes apart, the last one 5 minutes ago
+pub fn twelve_readings() -> Vec<HumitureData> {
+    let now = Local::now().with_nanosecond(0).unwrap();
+    (0..12)
+        .map(|i| {
+            let mut data = HumitureData::new(2, 0x0000111122223333, 1, 2, 20.0 + i as f32, 50.0);
+            data.ts = now - Duration::minutes(5 * (12 - i));
+            data
+        })
+        .collect()
+}
@@ -1,15 +1,24 @@
+mod common;
+
 #[cfg(test)]
 mod test_humiture {
 
-    use chrono::{Duration, Local};
-    use log::{debug, info};
-    use std::{env, sync::Once};
+    use chrono::{Duration, Local, TimeZone, Timelike};
+    use log::info;
+    use std::{
+        env,
+        sync::{Arc, Once},
+    };
     use tokio::test;
 
     use lgp_iot_db::models::humiture_data_v2::{
-        init_tdengine_humiture, query_humiture_by_date, query_humiture_by_group,
-        query_humiture_by_sn, HumitureData,
+        init_tdengine_humiture, insert_humiture_batch, query_humiture_by_date,
+        query_humiture_by_group, query_humiture_by_sn, DecodeOptions, HumitureData,
     };
+    use lgp_iot_db::query::{HumitureQuery, Order};
+    use lgp_iot_db::store::{MemoryStore, SensorStore};
+
+    use crate::common::FRAME_24;
 
     static INIT: Once = Once::new();
 
@@ -24,10 +33,23 @@ mod test_humiture {
     async fn test_to_bytes() {
         init();
 
-        let bytes =
-            HumitureData::new(0x00000001, 0x0000111122223333, 0, 0, -20.5, -10.5).to_bytes();
+        let bytes = HumitureData::new(0x00000001, 0x0000111122223333, 0, 0, -20.5, -10.5)
+            .to_bytes()
+            .unwrap();
         let result = HumitureData::from_bytes(&bytes, 1);
         assert_eq!(result.len(), 1);
+
+        // same frame as the encoder, values are rounded
+        let data = HumitureData::new(0x00000001, 0x0000111122223333, 0, 0, 21.46, 39.96);
+        let bytes = data.clone().to_bytes().unwrap();
+        assert_eq!(bytes, HumitureData::encode(&[data], 0).unwrap());
+        let result = HumitureData::from_bytes(&bytes, 1);
+        assert_eq!((result[0].temperature, result[0].humidity), (21.5, 40.0));
+
+        // a time the frame cannot hold
+        let mut data = HumitureData::new(0x00000001, 0x0000111122223333, 0, 0, 20.0, 50.0);
+        data.ts = Local.timestamp_opt(0, 0).unwrap();
+        assert!(data.to_bytes().is_err());
     }
 
     #[test]
@@ -36,8 +58,7 @@ mod test_humiture {
 
         init();
 
-        let hex_string = "5aa576aee6070000001f3b470000000002150b020a020400c200c200c200c200c300c300c300c400c500c600c600c700c700be00bf00bf00c000c100c100c200c300c400c600c602ab02af02b102b202b402b102b402b402af02ae02ac02a502a0029c0296028f028c0282027a026e026d0266025f025d62007c";
-        let bytes = hex::decode(hex_string).unwrap();
+        let bytes = hex::decode(FRAME_24).unwrap();
 
         let result = HumitureData::from_bytes(&bytes, 24);
 
@@ -49,6 +70,111 @@ mod test_humiture {
     }
 
     #[test]
+    async fn test_device_time() {
+        init();
+
+        let bytes = hex::decode(FRAME_24).unwrap();
+
+        // sent 2021-11-02 10:02:04, one sample every 5 minutes before that
+        let options = DecodeOptions {
+            max_skew: None,
+            ..Default::default()
+        };
+        let result = HumitureData::from_bytes_with(&bytes, 24, &options);
+        let sent = Local.with_ymd_and_hms(2021, 11, 2, 10, 2, 4).unwrap();
+        assert_eq!(result[0].ts, sent - Duration::minutes(120));
+        assert_eq!(result[23].ts, sent - Duration::minutes(5));
+
+        // far too old for the default skew, falls back to the receive time
+        let before = Local::now();
+        let result = HumitureData::from_bytes(&bytes, 24);
+        assert!(result[23].ts >= before - Duration::minutes(5));
+
+        // a single sample keeps its own time
+        let mut data = HumitureData::new(1, 0x0000111122223333, 0, 0, 20.0, 50.0);
+        data.ts = Local::now().with_nanosecond(0).unwrap() - Duration::minutes(3);
+        let result = HumitureData::from_bytes(&data.clone().to_bytes().unwrap(), 1);
+        assert_eq!(result[0].ts, data.ts);
+    }
+
+    #[test]
+    async fn test_memory_query() {
+        init();
+
+        let store = MemoryStore::new();
+        store.init().await.unwrap();
+
+        // one reading per minute over the last 30 minutes, 2 serial numbers
+        let now = Local::now();
+        for i in 0..30 {
+            let mut data =
+                HumitureData::new(1 + i % 2, 0x0000111122223333, 0, 0, 20.0 + i as f32, 50.0);
+            data.ts = now - Duration::minutes(i as i64);
+            assert_eq!(store.insert_humiture(&data).await.unwrap(), 1);
+        }
+        let other = HumitureData::new(3, 0x0000444455556666, 1, 0, 20.0, 50.0);
+        store.insert_humiture_batch(&[other]).await.unwrap();
+
+        // by date, newest first
+        let last = now - Duration::minutes(10);
+        let records = store
+            .query_humiture_range(
+                0x0000111122223333,
+                last.timestamp_millis(),
+                now.timestamp_millis(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 11);
+        assert!(records.windows(2).all(|w| w[0].ts >= w[1].ts));
+        assert_eq!(records[0].temperature, 20.0);
+
+        // latest N
+        let records = store
+            .query_humiture_latest(0x0000111122223333, 5)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 5);
+
+        // by group
+        let records = store
+            .query_humiture(&HumitureQuery::new().group(0).limit(100))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 30);
+
+        // by sn
+        let records = store
+            .query_humiture(&HumitureQuery::new().sn(2).limit(10))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 10);
+        assert!(records.iter().all(|r| r.sn == 2));
+
+        // oldest first, second page
+        let records = store
+            .query_humiture(
+                &HumitureQuery::new()
+                    .device(0x0000111122223333)
+                    .order(Order::Asc)
+                    .limit(10)
+                    .offset(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 10);
+        assert!(records.windows(2).all(|w| w[0].ts <= w[1].ts));
+        assert_eq!(records[0].temperature, 39.0);
+
+        // no tag columns outside TDengine
+        assert!(store
+            .query_humiture(&HumitureQuery::new().tag("site", "north"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    #[ignore = "requires the TDengine server at db.21up.cn"]
     async fn test_query() {
         init();
 
@@ -68,14 +194,59 @@ mod test_humiture {
             last.timestamp_millis(),
             now.timestamp_millis(),
         )
-        .await;
+        .await
+        .unwrap();
 
         assert_eq!(records.len(), 149);
 
-        let records = query_humiture_by_group(&taos, 0, 30).await;
+        let records = query_humiture_by_group(&taos, 0, 30).await.unwrap();
         assert_eq!(records.len(), 30);
 
-        let records = query_humiture_by_sn(&taos, 2, 10).await;
+        let records = query_humiture_by_sn(&taos, 2, 10).await.unwrap();
         assert_eq!(records.len(), 10);
     }
+
+    #[test]
+    #[ignore = "requires a local TDengine server"]
+    async fn test_insert_batch() {
+        init();
+
+        let taos = init_tdengine_humiture("taos://localhost:6030", "humiture")
+            .await
+            .unwrap();
+
+        // a 24 sample frame goes in with one statement execution
+        let datas = HumitureData::from_bytes(&hex::decode(FRAME_24).unwrap(), 24);
+
+        let rows = insert_humiture_batch(&datas, &taos).await.unwrap();
+        assert_eq!(rows, 24);
+    }
+
+    #[test]
+    #[ignore = "requires a local TDengine server"]
+    async fn test_shared_writer() {
+        init();
+
+        let taos = init_tdengine_humiture("taos://localhost:6030", "humiture")
+            .await
+            .unwrap();
+        let writer = Arc::new(taos.humiture_writer().await.unwrap());
+
+        // 4 tasks on one prepared statement, each with its own group
+        let mut tasks = Vec::new();
+        for group_id in 0..4 {
+            let writer = writer.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut rows = 0;
+                for _ in 0..10 {
+                    let data = HumitureData::new(1, 0x0000111122223333, group_id, 0, 20.0, 50.0);
+                    rows += writer.write(&data).await.unwrap();
+                }
+                rows
+            }));
+        }
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), 10);
+        }
+    }
 }
@@ -0,0 +1,42 @@
+#![cfg(feature = "postgres")]
+
+#[cfg(test)]
+mod test_postgres {
+
+    use chrono::{Duration, Local};
+    use lgp_iot_db::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+    use lgp_iot_db::store::{PostgresStore, SensorStore};
+    use std::env;
+    use tokio::test;
+
+    #[test]
+    #[ignore = "requires the PostgreSQL server in DATABASE_URL"]
+    async fn test_insert_query() {
+        dotenvy::dotenv().ok();
+        let store = PostgresStore::connect(&env::var("DATABASE_URL").unwrap()).unwrap();
+        store.init().await.unwrap();
+
+        let now = Local::now();
+        let datas: Vec<_> = (0..3)
+            .map(|i| {
+                let mut data = HumitureData::random();
+                data.ts = now - Duration::seconds(i);
+                data
+            })
+            .collect();
+        assert_eq!(store.insert_humiture_batch(&datas).await.unwrap(), 3);
+
+        let records = store
+            .query_humiture_latest(datas[0].device_id, 3)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 3);
+
+        let records = store.query_humiture_by_sn(datas[0].sn, 3).await.unwrap();
+        assert_eq!(records.len(), 3);
+
+        assert_eq!(store.insert_adxl(&AdxlData::_random()).await.unwrap(), 1);
+        let records = store.query_adxl_latest(9999, 1).await.unwrap();
+        assert_eq!(records.len(), 1);
+    }
+}
@@ -0,0 +1,444 @@
+mod common;
+
+#[cfg(test)]
+mod test_protocol {
+
+    use chrono::{Duration, Local, Timelike};
+    use lgp_iot_db::errors::Error;
+    use lgp_iot_db::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+    use lgp_iot_db::protocol::adxl;
+    use lgp_iot_db::protocol::humiture::sample_count;
+    use lgp_iot_db::protocol::humiture::{device_id_of, interval_minutes, payload_len};
+    use lgp_iot_db::protocol::{
+        crc8, decode, decode_frame, encode, CrcMode, DecodeError, DecodeOptions, Framer,
+    };
+
+    use crate::common::{twelve_frame, FRAME_24};
+
+    fn single() -> Vec<u8> {
+        HumitureData::new(0x00000001, 0x0000111122223333, 1, 2, 21.5, 40.0)
+            .to_bytes()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decode() {
+        let options = DecodeOptions::default();
+
+        let frame = decode_frame(&single(), 1, &options).unwrap();
+        assert_eq!(frame.device_id, 0x0000111122223333);
+        assert_eq!(frame.sn, 1);
+        assert_eq!((frame.group_id, frame.type_id), (1, 2));
+        assert_eq!(frame.samples.len(), 1);
+        assert_eq!(frame.samples[0].temperature, 21.5);
+        assert_eq!(frame.samples[0].humidity, 40.0);
+
+        let bytes = hex::decode(FRAME_24).unwrap();
+        let frame = decode_frame(&bytes, 24, &options).unwrap();
+        assert_eq!(frame.samples.len(), 24);
+        assert_eq!(frame.interval, 5);
+    }
+
+    #[test]
+    fn test_status() {
+        let options = DecodeOptions::default();
+
+        let mut data = HumitureData::new(0x00000001, 0x0000111122223333, 1, 2, 21.5, 40.0);
+        data.battery = 42;
+        data.people = true;
+        data.status = 0x03 << 1;
+        let frame = decode_frame(&data.to_bytes().unwrap(), 1, &options).unwrap();
+        assert_eq!(
+            (frame.battery, frame.people, frame.status),
+            (42, true, 0x07)
+        );
+        assert_eq!(frame.interval, 20);
+        let sample = &frame.samples[0];
+        assert_eq!(
+            (sample.battery, sample.people, sample.status),
+            (42, true, 0x07)
+        );
+
+        let frame = decode_frame(&twelve_frame(), 12, &options).unwrap();
+        assert_eq!((frame.battery, frame.people), (0x63, false));
+        assert!(frame.samples.iter().all(|s| s.status == 0x02));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let options = DecodeOptions::default();
+        let bytes = single();
+
+        assert_eq!(
+            decode_frame(&bytes[..2], 1, &options).unwrap_err(),
+            DecodeError::ShortBuffer {
+                needed: 4,
+                actual: 2
+            }
+        );
+        assert_eq!(
+            decode_frame(&bytes[..20], 1, &options).unwrap_err(),
+            DecodeError::ShortBuffer {
+                needed: 30,
+                actual: 20
+            }
+        );
+
+        let mut bad = bytes.clone();
+        bad[1] = 0xA6;
+        assert_eq!(
+            decode_frame(&bad, 1, &options).unwrap_err(),
+            DecodeError::BadHeader([0x5A, 0xA6])
+        );
+
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(
+            decode_frame(&long, 1, &options).unwrap_err(),
+            DecodeError::LengthMismatch {
+                expected: 30,
+                actual: 31
+            }
+        );
+
+        // a single sample frame is not a 12 sample one
+        assert_eq!(
+            decode_frame(&bytes, 12, &options).unwrap_err(),
+            DecodeError::LengthMismatch {
+                expected: 74,
+                actual: 30
+            }
+        );
+        assert_eq!(
+            decode_frame(&bytes, 3, &options).unwrap_err(),
+            DecodeError::UnsupportedSampleCount(3)
+        );
+
+        // the wrong `n` no longer panics in the legacy wrapper
+        assert!(HumitureData::from_bytes(&bytes, 24).is_empty());
+        assert!(HumitureData::from_bytes(&bytes[..20], 1).is_empty());
+    }
+
+    #[test]
+    fn test_crc() {
+        let strict = DecodeOptions::default();
+        let lenient = DecodeOptions {
+            crc: CrcMode::Lenient,
+            ..Default::default()
+        };
+
+        for (bytes, n) in [
+            (single(), 1),
+            (twelve_frame(), 12),
+            (hex::decode(FRAME_24).unwrap(), 24),
+        ] {
+            let frame = decode_frame(&bytes, n, &strict).unwrap();
+            assert!(frame.crc_ok);
+            assert_eq!(frame.samples.len(), n);
+
+            // flip a bit in the first temperature
+            let mut corrupt = bytes.clone();
+            corrupt[23] ^= 0x01;
+            let crc = bytes[bytes.len() - 1];
+            assert_eq!(
+                decode_frame(&corrupt, n, &strict).unwrap_err(),
+                DecodeError::CrcMismatch {
+                    expected: crc8(&corrupt[3..corrupt.len() - 1]),
+                    actual: crc
+                }
+            );
+
+            let frame = decode_frame(&corrupt, n, &lenient).unwrap();
+            assert!(!frame.crc_ok);
+            assert_eq!(frame.samples.len(), n);
+        }
+
+        assert_eq!(
+            decode_frame(&twelve_frame(), 12, &strict).unwrap().interval,
+            10
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        let options = DecodeOptions::default();
+        // whole seconds, the frame time has no more
+        let now = Local::now().with_nanosecond(0).unwrap();
+
+        for n in [12, 24] {
+            let datas: Vec<_> = (0..n)
+                .map(|i| {
+                    let mut data = HumitureData::new(
+                        7,
+                        0x0000111122223333,
+                        1,
+                        2,
+                        i as f32 - 5.5,
+                        40.0 + i as f32,
+                    );
+                    data.ts = now - Duration::minutes(10 * (n - i) as i64);
+                    data.battery = 80;
+                    data.people = true;
+                    data
+                })
+                .collect();
+
+            let bytes = HumitureData::encode(&datas, 1).unwrap();
+            assert_eq!(bytes.len(), payload_len(n) + 4);
+            let frame = decode(&bytes, &options).unwrap();
+            assert_eq!(frame.ts, now);
+            assert_eq!(frame.interval, interval_minutes(1));
+            assert_eq!((frame.battery, frame.people), (80, true));
+            assert_eq!(frame.samples.len(), n);
+            for (a, b) in datas.iter().zip(&frame.samples) {
+                assert_eq!(a.ts, b.ts);
+                assert_eq!(a.temperature, b.temperature);
+                assert_eq!(a.humidity, b.humidity);
+            }
+        }
+
+        // a captured frame comes out the same, but for its time
+        let bytes = hex::decode(FRAME_24).unwrap();
+        let options = DecodeOptions {
+            receive_time: true,
+            ..DecodeOptions::default()
+        };
+        let frame = decode(&bytes, &options).unwrap();
+        let again = encode(&frame.samples, 0).unwrap();
+        assert_eq!(again[..17], bytes[..17]);
+        assert_eq!(again[23..again.len() - 1], bytes[23..bytes.len() - 1]);
+
+        // a single reading keeps its own time
+        let mut data = HumitureData::new(1, 2, 3, 4, 21.5, 40.0);
+        data.ts = now;
+        let frame = decode(&encode(&[data], 0).unwrap(), &options).unwrap();
+        assert_eq!(frame.samples[0].temperature, 21.5);
+        let frame = decode(
+            &encode(&frame.samples, 0).unwrap(),
+            &DecodeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(frame.ts.timestamp(), now.timestamp());
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        let now = Local::now();
+        let datas: Vec<_> = (0..12)
+            .map(|i| {
+                let mut data = HumitureData::new(1, 2, 3, 4, 20.0, 50.0);
+                data.ts = now + Duration::minutes(5 * i);
+                data
+            })
+            .collect();
+        assert!(encode(&datas, 0).is_ok());
+
+        assert!(matches!(encode(&datas[..5], 0), Err(Error::Protocol(_))));
+        assert!(matches!(encode(&datas, 8), Err(Error::Validation(_))));
+        // spacing does not match the interval code
+        assert!(matches!(encode(&datas, 1), Err(Error::Validation(_))));
+
+        let mut mixed = datas.clone();
+        mixed[3].device_id = 9;
+        assert!(matches!(encode(&mixed, 0), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_sample_count() {
+        assert_eq!(sample_count(26), Some(1));
+        assert_eq!(sample_count(70), Some(12));
+        assert_eq!(sample_count(118), Some(24));
+        assert_eq!(sample_count(30), None);
+
+        // raw frames, no `n` needed
+        let options = DecodeOptions::default();
+        for (bytes, n) in [
+            (single(), 1),
+            (twelve_frame(), 12),
+            (hex::decode(FRAME_24).unwrap(), 24),
+        ] {
+            assert_eq!(decode(&bytes, &options).unwrap().samples.len(), n);
+            assert_eq!(HumitureData::decode(&bytes).unwrap().len(), n);
+        }
+
+        let mut unknown = single();
+        unknown[2] = 30;
+        unknown.extend_from_slice(&[0; 4]);
+        assert_eq!(
+            decode(&unknown, &options).unwrap_err(),
+            DecodeError::UnsupportedLength(30)
+        );
+        assert_eq!(
+            decode(&[0x5A, 0x00, 26, 0], &options).unwrap_err(),
+            DecodeError::BadHeader([0x5A, 0x00])
+        );
+        assert_eq!(
+            decode(&single()[..29], &options).unwrap_err(),
+            DecodeError::ShortBuffer {
+                needed: 30,
+                actual: 29
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_report() {
+        let bytes = single();
+
+        // bad frames can still be attributed to a device
+        assert_eq!(device_id_of(&bytes[..12]), Some(0x0000111122223333));
+        assert_eq!(device_id_of(&bytes[..5]), None);
+
+        let error: Error = DecodeError::UnsupportedSampleCount(3).into();
+        assert!(matches!(error, Error::Protocol(_)));
+        assert_eq!(
+            error.to_string(),
+            "protocol error: unsupported sample count: 3"
+        );
+    }
+
+    #[test]
+    fn test_framer() {
+        let frames = [single(), twelve_frame(), hex::decode(FRAME_24).unwrap()];
+        let mut stream = vec![0x00, 0x5A, 0x13];
+        for frame in &frames {
+            stream.extend_from_slice(frame);
+            // a header with a bad len and one with a bad checksum
+            stream.extend_from_slice(&[0x5A, 0xA5, 0x07, 0x5A]);
+        }
+        let mut corrupt = single();
+        corrupt[20] ^= 0xFF;
+        stream.extend_from_slice(&corrupt);
+        stream.extend_from_slice(&frames[0]);
+
+        // any chunk size gives the same frames
+        for size in [1, 3, 29, 64, stream.len()] {
+            let mut framer = Framer::new();
+            let mut out = Vec::new();
+            for chunk in stream.chunks(size) {
+                framer.push(chunk);
+                while let Some(frame) = framer.next_frame() {
+                    out.push(frame);
+                }
+            }
+            assert_eq!(out.len(), 4);
+            assert_eq!(out[..3], frames[..]);
+            assert_eq!(out[3], frames[0]);
+            assert_eq!(framer.buffered(), 0);
+            let stats = framer.stats();
+            assert_eq!(stats.frames, 4);
+            assert_eq!(stats.discarded as usize, 3 + 3 * 4 + corrupt.len());
+        }
+
+        // lenient mode leaves the bad checksum to the decoder
+        let mut framer = Framer::with_crc(CrcMode::Lenient);
+        framer.push(&corrupt);
+        assert_eq!(framer.next_frame(), Some(corrupt.clone()));
+
+        // half a frame waits for the rest
+        let mut framer = Framer::new();
+        framer.push(&frames[1][..40]);
+        assert_eq!(framer.next_frame(), None);
+        assert_eq!(framer.buffered(), 40);
+        framer.push(&frames[1][40..]);
+        assert_eq!(framer.next_frame().as_ref(), Some(&frames[1]));
+    }
+
+    fn adxl_samples(n: usize) -> Vec<AdxlData> {
+        let start = Local::now();
+        (0..n)
+            .map(|i| AdxlData {
+                device_id: 9999,
+                ts: start + Duration::milliseconds(250 * i as i64),
+                x: 0.125 * i as f32,
+                y: -1.5,
+                z: 0.98,
+                t: 23.45,
+                bat: 87.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_adxl() {
+        for n in [1, 5, adxl::MAX_SAMPLES] {
+            let samples = adxl_samples(n);
+            let bytes = AdxlData::encode(&samples).unwrap();
+            assert_eq!(bytes.len(), adxl::payload_len(n) + 4);
+
+            let frame = adxl::decode(&bytes, CrcMode::Strict).unwrap();
+            assert_eq!(frame.device_id, 9999);
+            assert_eq!(frame.interval, if n == 1 { 0 } else { 250 });
+            assert!(frame.crc_ok);
+            assert_eq!(frame.samples.len(), n);
+            for (a, b) in samples.iter().zip(&frame.samples) {
+                assert_eq!(a.ts.timestamp_millis(), b.ts.timestamp_millis());
+                assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+                assert!((a.t - b.t).abs() < 0.001);
+                assert_eq!(a.bat, b.bat);
+            }
+        }
+
+        let sample = adxl_samples(1).remove(0);
+        let decoded = AdxlData::from_bytes(&sample.to_bytes()).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].x, sample.x);
+    }
+
+    #[test]
+    fn test_adxl_errors() {
+        let samples = adxl_samples(3);
+        let bytes = AdxlData::encode(&samples).unwrap();
+
+        assert!(matches!(
+            AdxlData::encode(&adxl_samples(17)),
+            Err(Error::Protocol(_))
+        ));
+        assert!(matches!(AdxlData::encode(&[]), Err(Error::Protocol(_))));
+        let mut uneven = samples.clone();
+        uneven[2].ts += Duration::milliseconds(1);
+        assert!(matches!(
+            AdxlData::encode(&uneven),
+            Err(Error::Validation(_))
+        ));
+        let mut mixed = samples.clone();
+        mixed[1].device_id = 1;
+        assert!(matches!(
+            AdxlData::encode(&mixed),
+            Err(Error::Validation(_))
+        ));
+
+        // humiture frames are not ADXL frames
+        assert_eq!(
+            AdxlData::from_bytes(&single()).unwrap_err(),
+            DecodeError::BadHeader([0x5A, 0xA5])
+        );
+        assert_eq!(
+            AdxlData::from_bytes(&bytes[..30]).unwrap_err(),
+            DecodeError::ShortBuffer {
+                needed: bytes.len(),
+                actual: 30
+            }
+        );
+
+        let mut count = bytes.clone();
+        count[17] = 4;
+        assert_eq!(
+            adxl::decode(&count, CrcMode::Strict).unwrap_err(),
+            DecodeError::LengthMismatch {
+                expected: adxl::payload_len(4) + 4,
+                actual: bytes.len()
+            }
+        );
+
+        let mut corrupt = bytes.clone();
+        corrupt[20] ^= 0xFF;
+        assert!(matches!(
+            adxl::decode(&corrupt, CrcMode::Strict),
+            Err(DecodeError::CrcMismatch { .. })
+        ));
+        let frame = adxl::decode(&corrupt, CrcMode::Lenient).unwrap();
+        assert!(!frame.crc_ok);
+        assert_eq!(frame.samples.len(), 3);
+    }
+}
@@ -0,0 +1,92 @@
+mod common;
+
+#[cfg(test)]
+mod test_serial {
+
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::fd::FromRawFd;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::test;
+
+    use lgp_iot_db::models::humiture_data_v2::HumitureData;
+    use lgp_iot_db::protocol::humiture::device_id_of;
+    use lgp_iot_db::query::HumitureQuery;
+    use lgp_iot_db::serial::ingest;
+    use lgp_iot_db::store::{BufferConfig, BufferedWriter, MemoryStore, SensorStore};
+
+    use crate::common::FRAME_24;
+
+    // master end and the path of the slave end of a new pseudo terminal
+    fn pty() -> (File, String) {
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0);
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+            let mut name = [0 as libc::c_char; 64];
+            assert_eq!(libc::ptsname_r(fd, name.as_mut_ptr(), name.len()), 0);
+            let name = CStr::from_ptr(name.as_ptr()).to_str().unwrap().to_string();
+            (File::from_raw_fd(fd), name)
+        }
+    }
+
+    async fn count(store: &MemoryStore, device_id: i64) -> usize {
+        let query = HumitureQuery::new().device(device_id);
+        store.query_humiture(&query).await.unwrap().len()
+    }
+
+    #[test]
+    async fn test_ingest() {
+        let (mut master, name) = pty();
+        let store = Arc::new(MemoryStore::new());
+        let writer = BufferedWriter::spawn(
+            store.clone(),
+            BufferConfig {
+                max_delay: Duration::from_millis(10),
+                ..BufferConfig::default()
+            },
+        );
+        let task = tokio::spawn({
+            let sender = writer.sender();
+            async move { ingest(&name, 115_200, sender).await }
+        });
+
+        // opening the port flushes its input, send until a frame gets through
+        let single = HumitureData::new(1, 0x0000444455556666, 3, 1, 21.5, 40.0)
+            .to_bytes()
+            .unwrap();
+        for _ in 0..100 {
+            master.write_all(&single).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if count(&store, 0x0000444455556666).await > 0 {
+                break;
+            }
+        }
+        assert!(count(&store, 0x0000444455556666).await > 0);
+
+        // split across writes, behind some garbage
+        let frame = hex::decode(FRAME_24).unwrap();
+        let mut stream = vec![0x00, 0x13];
+        stream.extend_from_slice(&frame);
+        for chunk in stream.chunks(40) {
+            master.write_all(chunk).unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let device_id = device_id_of(&frame).unwrap();
+        for _ in 0..100 {
+            if count(&store, device_id).await == 24 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(count(&store, device_id).await, 24);
+
+        task.abort();
+        let _ = task.await;
+        writer.shutdown().await.unwrap();
+    }
+}
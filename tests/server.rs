@@ -0,0 +1,105 @@
+mod common;
+
+#[cfg(test)]
+mod test_server {
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use lgp_iot_db::models::humiture_data_v2::HumitureData;
+    use lgp_iot_db::protocol::crc8;
+    use lgp_iot_db::protocol::humiture::{encode_ack, ACK_LEN};
+    use lgp_iot_db::query::HumitureQuery;
+    use lgp_iot_db::server::{ServerConfig, TcpServer};
+    use lgp_iot_db::store::{MemoryStore, SensorStore};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
+    use tokio::test;
+
+    use crate::common::FRAME_24;
+
+    #[test]
+    async fn test_ingest() {
+        let store = Arc::new(MemoryStore::new());
+        let config = ServerConfig {
+            ack: true,
+            ..ServerConfig::default()
+        };
+        let server = Arc::new(
+            TcpServer::bind("127.0.0.1:0", store.clone(), config)
+                .await
+                .unwrap(),
+        );
+        let addr = server.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let task = tokio::spawn({
+            let server = server.clone();
+            async move {
+                server
+                    .run_until(async {
+                        let _ = stopped.await;
+                    })
+                    .await
+            }
+        });
+
+        let single = HumitureData::new(1, 0x0000444455556666, 3, 1, 21.5, 40.0)
+            .to_bytes()
+            .unwrap();
+        let multi = hex::decode(FRAME_24).unwrap();
+
+        // two gateways, frames split and joined across writes, some garbage
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        let mut stream = vec![0x00, 0x13];
+        stream.extend_from_slice(&single);
+        stream.extend_from_slice(&multi);
+        for chunk in stream.chunks(40) {
+            first.write_all(chunk).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        second.write_all(&single).await.unwrap();
+
+        // one ACK per frame
+        let mut acks = vec![0; 2 * (ACK_LEN + 4)];
+        first.read_exact(&mut acks).await.unwrap();
+        assert_eq!(acks[..ACK_LEN + 4], encode_ack(0x0000444455556666, 1, true));
+        let ack = &acks[ACK_LEN + 4..];
+        assert_eq!(&ack[..3], &[0x5A, 0xA5, ACK_LEN as u8]);
+        assert_eq!(ack[ACK_LEN + 2], 0);
+        assert_eq!(crc8(&ack[3..ACK_LEN + 3]), ack[ACK_LEN + 3]);
+        let mut ack = vec![0; ACK_LEN + 4];
+        second.read_exact(&mut ack).await.unwrap();
+
+        let records = store
+            .query_humiture(&HumitureQuery::new().device(0x0000444455556666))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        let records = store.query_humiture(&HumitureQuery::new()).await.unwrap();
+        assert_eq!(records.len(), 26);
+
+        let connections = server.connections();
+        assert_eq!(connections.len(), 2);
+        let stats = connections
+            .iter()
+            .find(|c| c.peer == first.local_addr().unwrap())
+            .unwrap();
+        assert_eq!((stats.frames, stats.readings, stats.discarded), (2, 25, 2));
+
+        // closed connections are dropped from the stats
+        drop(first);
+        drop(second);
+        for _ in 0..50 {
+            if server.connections().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(server.connections().is_empty());
+
+        stop.send(()).unwrap();
+        task.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+#[cfg(test)]
+mod test_spool {
+
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use chrono::{DateTime, Local};
+    use serde_derive::Serialize;
+    use tokio::test;
+
+    use lgp_iot_db::errors::{Error, Result};
+    use lgp_iot_db::models::{
+        adxl_data_v2::AdxlData, humiture_data_v2::HumitureData, reading::Reading,
+    };
+    use lgp_iot_db::query::{AdxlQuery, HumitureQuery, Order};
+    use lgp_iot_db::store::{MemoryStore, SensorStore, Spool, SpoolingStore};
+    use lgp_iot_db::tdengine::Backoff;
+
+    fn spool_path(name: &str) -> PathBuf {
+        let path =
+            env::temp_dir().join(format!("lgp-iot-db-{}-{}.spool", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn humiture(sn: i32) -> HumitureData {
+        HumitureData::new(sn, 0x0000111122223333, 0, 0, 20.0, 50.0)
+    }
+
+    // memory store that fails every write while `down` is set
+    #[derive(Default)]
+    struct FlakyStore {
+        down: AtomicBool,
+        calls: AtomicUsize,
+        inner: MemoryStore,
+    }
+
+    impl FlakyStore {
+        fn check(&self) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Err(Error::connection("database is down"));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl SensorStore for FlakyStore {
+        async fn init(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn insert_humiture(&self, data: &HumitureData) -> Result<usize> {
+            self.check()?;
+            self.inner.insert_humiture(data).await
+        }
+
+        async fn query_humiture(&self, query: &HumitureQuery) -> Result<Vec<HumitureData>> {
+            self.inner.query_humiture(query).await
+        }
+
+        async fn insert_adxl(&self, data: &AdxlData) -> Result<usize> {
+            self.check()?;
+            self.inner.insert_adxl(data).await
+        }
+
+        async fn query_adxl(&self, query: &AdxlQuery) -> Result<Vec<AdxlData>> {
+            self.inner.query_adxl(query).await
+        }
+    }
+
+    #[test]
+    async fn test_spool_file() {
+        let path = spool_path("file");
+
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        assert!(spool.is_empty());
+        spool
+            .append(&[humiture(1).into(), AdxlData::_random().into()])
+            .unwrap();
+        spool.append(&[humiture(2).into()]).unwrap();
+        drop(spool);
+
+        // a record cut short by a crash
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[200, 0, 0, 0, 1, 2, 3]).unwrap();
+        drop(file);
+
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        let readings = spool.read_all().unwrap();
+        assert_eq!(readings.len(), 3);
+        assert!(matches!(&readings[0], Reading::Humiture(data) if data.sn == 1));
+        assert!(matches!(&readings[1], Reading::Adxl(_)));
+        assert!(matches!(&readings[2], Reading::Humiture(data) if data.sn == 2));
+
+        // appends after the torn record are read back
+        spool.append(&[humiture(3).into()]).unwrap();
+        drop(spool);
+        let spool = Spool::open(&path, 1 << 20).unwrap();
+        let readings = spool.read_all().unwrap();
+        assert_eq!(readings.len(), 4);
+        assert!(matches!(&readings[3], Reading::Humiture(data) if data.sn == 3));
+        assert_eq!(spool.size(), fs::metadata(&path).unwrap().len());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    // `Reading` as spooled before the humiture battery, people and status
+    #[derive(Serialize)]
+    enum ReadingV1 {
+        Humiture(HumitureDataV1),
+    }
+
+    #[derive(Serialize)]
+    struct HumitureDataV1 {
+        ts: DateTime<Local>,
+        sn: i32,
+        device_id: i64,
+        group_id: i32,
+        type_id: i32,
+        temperature: f32,
+        humidity: f32,
+    }
+
+    #[test]
+    async fn test_spool_upgrade() {
+        let path = spool_path("upgrade");
+
+        let mut file = fs::File::create(&path).unwrap();
+        for sn in 1..=2 {
+            let record = bincode::serialize(&ReadingV1::Humiture(HumitureDataV1 {
+                ts: Local::now(),
+                sn,
+                device_id: 0x0000111122223333,
+                group_id: 0,
+                type_id: 0,
+                temperature: 20.0,
+                humidity: 50.0,
+            }))
+            .unwrap();
+            file.write_all(&(record.len() as u32).to_le_bytes())
+                .unwrap();
+            file.write_all(&record).unwrap();
+        }
+        drop(file);
+
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        let readings = spool.read_all().unwrap();
+        assert_eq!(readings.len(), 2);
+        for (reading, sn) in readings.iter().zip(1..) {
+            match reading {
+                Reading::Humiture(data) => {
+                    assert_eq!(data.sn, sn);
+                    assert_eq!(data.temperature, 20.0);
+                    assert_eq!((data.battery, data.people, data.status), (0, false, 0));
+                }
+                _ => panic!("expected a humiture reading"),
+            }
+        }
+
+        spool.append(&[humiture(3).into()]).unwrap();
+        drop(spool);
+        let spool = Spool::open(&path, 1 << 20).unwrap();
+        assert_eq!(spool.read_all().unwrap().len(), 3);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    async fn test_disk_budget() {
+        let path = spool_path("budget");
+
+        let mut spool = Spool::open(&path, 256).unwrap();
+        let batch: Vec<Reading> = (0..20).map(|i| humiture(i).into()).collect();
+        assert!(spool.append(&batch).is_err());
+        assert!(spool.is_empty());
+
+        spool.append(&batch[..1]).unwrap();
+        assert!(spool.size() <= 256);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    async fn test_replay() {
+        let path = spool_path("replay");
+
+        let store = SpoolingStore::new(FlakyStore::default(), Spool::open(&path, 1 << 20).unwrap())
+            .with_backoff(Backoff {
+                initial: Duration::ZERO,
+                ..Backoff::default()
+            });
+
+        // database down: writes are accepted and spooled
+        store.inner().down.store(true, Ordering::SeqCst);
+        for sn in 0..5 {
+            assert_eq!(store.insert_humiture(&humiture(sn)).await.unwrap(), 1);
+        }
+        store.insert_adxl(&AdxlData::_random()).await.unwrap();
+        assert!(store.pending().await > 0);
+        assert!(store.replay().await.is_err());
+
+        // database back: the spool goes first, then the new reading
+        store.inner().down.store(false, Ordering::SeqCst);
+        store.insert_humiture(&humiture(5)).await.unwrap();
+        assert_eq!(store.pending().await, 0);
+
+        let records = store
+            .query_humiture(&HumitureQuery::new().order(Order::Asc))
+            .await
+            .unwrap();
+        let sns: Vec<i32> = records.iter().map(|r| r.sn).collect();
+        assert_eq!(sns, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(store.query_adxl(&AdxlQuery::new()).await.unwrap().len(), 1);
+
+        // nothing left to replay
+        assert_eq!(store.replay().await.unwrap(), 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    async fn test_replay_backoff() {
+        let path = spool_path("backoff");
+
+        let store = SpoolingStore::new(FlakyStore::default(), Spool::open(&path, 1 << 20).unwrap())
+            .with_backoff(Backoff {
+                initial: Duration::from_millis(200),
+                ..Backoff::default()
+            });
+
+        // one failed write, the ones after it wait for the backoff
+        store.inner().down.store(true, Ordering::SeqCst);
+        for sn in 0..10 {
+            store.insert_humiture(&humiture(sn)).await.unwrap();
+        }
+        assert_eq!(store.inner().calls.load(Ordering::SeqCst), 1);
+
+        // replayed with the first write after the backoff, in batches
+        store.inner().down.store(false, Ordering::SeqCst);
+        store.insert_humiture(&humiture(10)).await.unwrap();
+        assert!(store.pending().await > 0);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        store.insert_humiture(&humiture(11)).await.unwrap();
+        assert_eq!(store.pending().await, 0);
+
+        let records = store
+            .query_humiture(&HumitureQuery::new().order(Order::Asc))
+            .await
+            .unwrap();
+        let sns: Vec<i32> = records.iter().map(|r| r.sn).collect();
+        assert_eq!(sns, (0..12).collect::<Vec<_>>());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    async fn test_partial_replay() {
+        let path = spool_path("partial");
+
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        let readings: Vec<Reading> = (0..3)
+            .map(|sn| humiture(sn).into())
+            .chain((0..2).map(|_| AdxlData::_random().into()))
+            .collect();
+        spool.append(&readings).unwrap();
+
+        // the records after the first one are kept
+        let (_, end) = spool.records().unwrap().next().unwrap().unwrap();
+        spool.discard_front(end).unwrap();
+        let left = spool.read_all().unwrap();
+        assert_eq!(left.len(), 4);
+        assert!(matches!(&left[0], Reading::Humiture(data) if data.sn == 1));
+        assert_eq!(spool.size(), fs::metadata(&path).unwrap().len());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
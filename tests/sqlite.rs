@@ -0,0 +1,119 @@
+#![cfg(feature = "sqlite")]
+
+#[cfg(test)]
+mod test_sqlite {
+
+    use chrono::{Duration, Local};
+    use lgp_iot_db::models::{adxl_data_v2::AdxlData, humiture_data_v2::HumitureData};
+    use lgp_iot_db::query::HumitureQuery;
+    use lgp_iot_db::store::{SensorStore, SqliteStore};
+    use std::env;
+    use tokio::test;
+
+    #[test]
+    async fn test_humiture() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store.init().await.unwrap();
+
+        let now = Local::now();
+        let datas: Vec<_> = (0..24)
+            .map(|i| {
+                let mut data =
+                    HumitureData::new(1 + i % 2, 0x0000111122223333, 3, 1, i as f32, 50.0);
+                data.ts = now - Duration::minutes(5 * i as i64);
+                data
+            })
+            .collect();
+        assert_eq!(store.insert_humiture_batch(&datas).await.unwrap(), 24);
+        let mut data = HumitureData::new(9, 0x0000444455556666, 4, 1, 1.0, 1.0);
+        data.battery = 17;
+        data.people = true;
+        data.status = 0x03;
+        store.insert_humiture(&data).await.unwrap();
+
+        // by date
+        let last = now - Duration::minutes(60);
+        let records = store
+            .query_humiture_range(
+                0x0000111122223333,
+                last.timestamp_millis(),
+                now.timestamp_millis(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 13);
+        assert_eq!(records[0].ts.timestamp_millis(), now.timestamp_millis());
+        assert!(records.windows(2).all(|w| w[0].ts > w[1].ts));
+
+        // latest N, by group, by sn
+        let records = store
+            .query_humiture_latest(0x0000111122223333, 5)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 5);
+        let records = store.query_humiture_by_group(3, 100).await.unwrap();
+        assert_eq!(records.len(), 24);
+        let records = store.query_humiture_by_sn(2, 100).await.unwrap();
+        assert_eq!(records.len(), 12);
+        let records = store
+            .query_humiture(&HumitureQuery::new().group(4))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].sn, 9);
+        assert_eq!(
+            (records[0].battery, records[0].people, records[0].status),
+            (17, true, 0x03)
+        );
+    }
+
+    #[test]
+    async fn test_upgrade() {
+        // a database from before battery / people / status
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE humiture (ts INTEGER NOT NULL, sn INTEGER NOT NULL,
+                device_id INTEGER NOT NULL, group_id INTEGER NOT NULL, type_id INTEGER NOT NULL,
+                temperature REAL NOT NULL, humidity REAL NOT NULL);
+             INSERT INTO humiture VALUES (1000, 1, 2, 3, 4, 20.0, 50.0);",
+        )
+        .unwrap();
+        let store = SqliteStore::from_connection(conn);
+        store.init().await.unwrap();
+        // twice is fine
+        store.init().await.unwrap();
+
+        let records = store
+            .query_humiture(&HumitureQuery::new().device(2))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            (records[0].battery, records[0].people, records[0].status),
+            (0, false, 0)
+        );
+    }
+
+    #[test]
+    async fn test_adxl_persisted() {
+        let path = env::temp_dir().join(format!("lgp-iot-db-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            store.init().await.unwrap();
+            let datas: Vec<_> = (0..10)
+                .map(|i| AdxlData::test_wave(1.0, i as f32 * 36.0))
+                .collect();
+            assert_eq!(store.insert_adxl_batch(&datas).await.unwrap(), 10);
+        }
+
+        // reopen, data survived
+        let store = SqliteStore::open(&path).unwrap();
+        store.init().await.unwrap();
+        let records = store.query_adxl_latest(9999, 100).await.unwrap();
+        assert_eq!(records.len(), 10);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}